@@ -1,21 +1,947 @@
-use std::sync::Mutex;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufWriter, Write},
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
 
 use lazy_static::lazy_static;
-use oracle::{sql_type::ToSql, Connection};
+use oracle::{
+    sql_type::{Blob, Clob, IntervalDS, IntervalYM, Lob, NClob, OracleType, ToSql},
+    ColumnIndex, Connection, Connector, InitParams, Row, Statement, Version,
+};
+use serde::Deserialize;
+use serde_json::json;
 use testangel_engine::*;
 use thiserror::Error;
 
-#[derive(Default)]
 struct State {
+    /// The defaults from the engine configuration file.
+    config: EngineConfig,
+    /// Why the engine configuration file couldn't be read, if it couldn't.
+    config_error: Option<String>,
     conn: Option<Connection>,
+    /// When the connection was last used.
+    last_used: Option<Instant>,
+    /// Whether statements may have changed data since the last commit or
+    /// rollback, when autocommit is off.
+    uncommitted: bool,
     params: Vec<SqlValue>,
+    /// The result of the last query returning a single value.
+    last_result: Option<SqlValue>,
+    /// Whether string parameters are bound as NVARCHAR2.
+    force_nchar: bool,
+    /// The details the current connection was opened with, so that further
+    /// connections can be opened to the same database.
+    credentials: Option<Credentials>,
+    /// Triggers disabled by this engine, with the name of the connection they
+    /// were disabled on, so they can be restored at cleanup.
+    disabled_triggers: Vec<(String, String)>,
+    settings: ConnectionSettings,
+    /// The result cache hint to add to queries, if any.
+    result_cache_hint: Option<&'static str>,
+    /// Further optimizer hints to add to queries, or empty for none.
+    query_hints: String,
+    /// The most rows fetched by a query unless it sets its own limit, or
+    /// `None` for no limit.
+    max_rows: Option<usize>,
+    /// The approximate number of bytes of results and parameters the engine
+    /// will hold in memory at once.
+    memory_budget: usize,
+    /// Limits how many statements run at once on the engine's additional
+    /// connections, or `None` for no limit.
+    concurrency: Option<Arc<Semaphore>>,
+    /// Whether the results of identical queries are reused.
+    query_cache_enabled: bool,
+    /// Cached query results, by [`State::cache_key`].
+    query_cache: HashMap<String, CachedResult>,
+    /// Queries running on their own connections, by handle.
+    background_queries: HashMap<String, BackgroundQuery>,
+    metrics: Metrics,
+    /// The `${env:NAME}` text of parameters added with environment variables
+    /// resolved, by resolved value, so recordings don't reveal the values.
+    env_ref_params: HashMap<String, String>,
+    /// Values stashed by name for use later in the flow.
+    variables: HashMap<String, String>,
+    /// Where executed statements are being recorded, if they are.
+    recording: Option<Recording>,
+    /// The name of the connection statements currently run on.
+    active_connection: String,
+    /// Named connections other than the active one.
+    other_connections: HashMap<String, NamedConnection>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        let (config, config_error) = match EngineConfig::load() {
+            Ok(config) => (config, None),
+            Err(e) => (EngineConfig::default(), Some(e)),
+        };
+        Self {
+            settings: ConnectionSettings {
+                stmt_cache_size: config.stmt_cache_size.filter(|&n| n > 0),
+                prefetch_rows: config.prefetch_rows.filter(|&n| n > 0),
+                autocommit: config.autocommit,
+                call_timeout: config
+                    .call_timeout
+                    .filter(|&n| n > 0)
+                    .map(Duration::from_secs),
+            },
+            max_rows: config.max_rows.filter(|&n| n > 0),
+            memory_budget: config
+                .memory_budget_mb
+                .filter(|&n| n > 0)
+                .unwrap_or(DEFAULT_MEMORY_BUDGET_MB)
+                * 1024
+                * 1024,
+            concurrency: config
+                .max_concurrent_statements
+                .filter(|&n| n > 0)
+                .map(|n| Arc::new(Semaphore::new(n))),
+            config,
+            config_error,
+            conn: None,
+            last_used: None,
+            params: vec![],
+            credentials: None,
+            disabled_triggers: vec![],
+            result_cache_hint: None,
+            query_hints: String::new(),
+            uncommitted: false,
+            last_result: None,
+            force_nchar: false,
+            query_cache_enabled: false,
+            query_cache: HashMap::new(),
+            background_queries: HashMap::new(),
+            metrics: Metrics::default(),
+            env_ref_params: HashMap::new(),
+            variables: HashMap::new(),
+            recording: None,
+            active_connection: DEFAULT_CONNECTION.to_string(),
+            other_connections: HashMap::new(),
+        }
+    }
+}
+
+impl State {
+    /// Get the current connection, opening it first if the engine was
+    /// connected lazily. A connection that has been idle for a while is
+    /// checked with a round trip first, and replaced if it has died, unless
+    /// that would lose uncommitted changes.
+    fn connection(&mut self, evidence: &mut Vec<Evidence>) -> Result<&Connection, EngineError> {
+        let idle = self.last_used.filter(|t| t.elapsed() >= IDLE_PING_INTERVAL);
+        if let (Some(last_used), Some(conn)) = (idle, &self.conn) {
+            if self.credentials.is_some() && conn.ping().is_err() {
+                self.conn = None;
+                self.metrics.reconnects += 1;
+                if std::mem::take(&mut self.uncommitted) {
+                    return Err(EngineError::ConnectionLost(last_used.elapsed().as_secs()));
+                }
+                evidence.push(Evidence {
+                    label: "Reconnected".to_string(),
+                    content: EvidenceContent::Textual(format!(
+                        "The connection died after being idle for {}s, so a new session was opened. \
+                        Session settings such as application contexts and consumer groups were reset.",
+                        last_used.elapsed().as_secs(),
+                    )),
+                });
+            }
+        }
+        self.last_used = Some(Instant::now());
+
+        if self.conn.is_none() {
+            let credentials = self
+                .credentials
+                .as_ref()
+                .ok_or(EngineError::NotYetConnected)?;
+            self.conn = Some(credentials.connect(&self.settings)?);
+        }
+        self.conn.as_ref().ok_or(EngineError::NotYetConnected)
+    }
+
+    /// Note a statement run on the current connection, tracking whether it
+    /// may have left changes uncommitted.
+    fn track_statement(&mut self, sql: &str) {
+        if self.settings.autocommit {
+            return;
+        }
+        let keyword = sql
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match keyword.as_str() {
+            "commit" | "rollback" => self.uncommitted = false,
+            "select" | "with" => (),
+            _ => self.uncommitted = true,
+        }
+    }
+
+    /// Make the named connection the one statements run on, setting aside
+    /// the current one under its name.
+    fn use_connection(&mut self, name: &str) -> Result<(), EngineError> {
+        if name == self.active_connection {
+            return Ok(());
+        }
+        let next = self
+            .other_connections
+            .remove(name)
+            .ok_or_else(|| EngineError::UnknownConnection(name.to_string()))?;
+
+        let previous = std::mem::replace(&mut self.active_connection, name.to_string());
+        if let Some(credentials) = self.credentials.take() {
+            self.other_connections.insert(
+                previous,
+                NamedConnection {
+                    conn: self.conn.take(),
+                    credentials,
+                    last_used: self.last_used,
+                    uncommitted: self.uncommitted,
+                },
+            );
+        }
+        self.conn = next.conn;
+        self.credentials = Some(next.credentials);
+        self.last_used = next.last_used;
+        self.uncommitted = next.uncommitted;
+        Ok(())
+    }
+
+    /// Note that a connection's transaction was committed or rolled back.
+    fn finish_transaction(&mut self, name: &str) {
+        if name == self.active_connection {
+            self.uncommitted = false;
+        } else if let Some(named) = self.other_connections.get_mut(name) {
+            named.uncommitted = false;
+        }
+    }
+
+    /// The names of every open connection, the active one first.
+    fn open_connection_names(&self) -> Vec<String> {
+        let mut names = self
+            .other_connections
+            .iter()
+            .filter(|(_, named)| named.conn.is_some())
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        if self.conn.is_some() {
+            names.insert(0, self.active_connection.clone());
+        }
+        names
+    }
+
+    /// Get a connection by name, opening it first if it was never used.
+    fn named_connection(
+        &mut self,
+        name: &str,
+        evidence: &mut Vec<Evidence>,
+    ) -> Result<&Connection, EngineError> {
+        if name == self.active_connection {
+            return self.connection(evidence);
+        }
+        let named = self
+            .other_connections
+            .get_mut(name)
+            .ok_or_else(|| EngineError::UnknownConnection(name.to_string()))?;
+        if named.conn.is_none() {
+            named.conn = Some(named.credentials.connect(&self.settings)?);
+        }
+        named.last_used = Some(Instant::now());
+        named.conn.as_ref().ok_or(EngineError::NotYetConnected)
+    }
+
+    /// Prepare a statement on the current connection with the configured
+    /// tuning applied, optionally fetching rows in arrays of a given size. With
+    /// `lob_locators`, LOB columns are fetched as locators so their length can
+    /// be checked before their contents are read.
+    fn prepare(
+        &mut self,
+        sql: &str,
+        fetch_array_size: Option<u32>,
+        lob_locators: bool,
+        evidence: &mut Vec<Evidence>,
+    ) -> Result<Statement, EngineError> {
+        let prefetch_rows = self.settings.prefetch_rows;
+        let conn = self.connection(evidence)?;
+        let mut builder = conn.statement(sql);
+        if let Some(rows) = prefetch_rows {
+            builder.prefetch_rows(rows);
+        }
+        if let Some(size) = fetch_array_size {
+            builder.fetch_array_size(size);
+        }
+        if lob_locators {
+            builder.lob_locator();
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Check that holding a value of `bytes` bytes in memory, alongside the
+    /// parameters already added and the cached query results, stays within
+    /// the memory budget. The query cache is emptied to make room if needed.
+    fn check_memory(&mut self, bytes: usize) -> Result<(), EngineError> {
+        let params = self.params.iter().map(SqlValue::approx_size).sum::<usize>();
+        if bytes + params + self.query_cache_size() > self.memory_budget {
+            self.query_cache.clear();
+        }
+        let used = bytes + params;
+        if used > self.memory_budget {
+            return Err(EngineError::ResultTooLarge(used, self.memory_budget));
+        }
+        Ok(())
+    }
+
+    /// The key to cache the result of a query under, or `None` if caching is
+    /// disabled or the query might not only read data.
+    fn cache_key(&self, query: &str, column: &str, params: &[SqlValue]) -> Option<String> {
+        let keyword = query.split_whitespace().next()?.to_ascii_lowercase();
+        if !self.query_cache_enabled || !matches!(keyword.as_str(), "select" | "with") {
+            return None;
+        }
+        Some(format!(
+            "{}\0{query}\0{column}\0{params:?}",
+            self.active_connection
+        ))
+    }
+
+    /// The approximate number of bytes held by cached query results.
+    fn query_cache_size(&self) -> usize {
+        self.query_cache
+            .iter()
+            .map(|(key, result)| key.len() + result.approx_size())
+            .sum()
+    }
+
+    /// Cache the result of a query, unless it doesn't fit in the memory
+    /// budget even with the cache emptied.
+    fn cache_result(&mut self, key: String, result: CachedResult) {
+        if self.check_memory(key.len() + result.approx_size()).is_ok() {
+            self.query_cache.insert(key, result);
+        }
+    }
+
+    /// Forget the cached results of queries run on a connection, when it is
+    /// pointed at a different database.
+    fn forget_cached_results(&mut self, connection: &str) {
+        let prefix = format!("{connection}\0");
+        self.query_cache.retain(|key, _| !key.starts_with(&prefix));
+    }
+
+    /// Append a statement and its parameters to the SQL recording, if one is
+    /// running.
+    fn record(&mut self, statement: &str, params: &[SqlValue]) -> Result<(), EngineError> {
+        let params = params
+            .iter()
+            .map(|value| self.recorded_value(value))
+            .collect::<Vec<_>>();
+        self.write_recording(json!({ "statement": statement, "params": params }))
+    }
+
+    /// Append a batch statement and its rows of parameters to the SQL
+    /// recording, if one is running.
+    fn record_batch(&mut self, statement: &str, rows: &[Vec<SqlValue>]) -> Result<(), EngineError> {
+        let rows = rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|value| self.recorded_value(value))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        self.write_recording(json!({ "statement": statement, "batch": rows }))
+    }
+
+    /// A parameter as JSON for a SQL recording, with values that came from
+    /// environment variables recorded as the `${env:NAME}` text they were
+    /// given as.
+    fn recorded_value(&self, value: &SqlValue) -> serde_json::Value {
+        match value {
+            SqlValue::String(s) => self
+                .env_ref_params
+                .get(s)
+                .map_or_else(|| value.to_json(), |text| json!(text)),
+            SqlValue::NChar(NChar(s)) => self
+                .env_ref_params
+                .get(s)
+                .map_or_else(|| value.to_json(), |text| json!({ "nchar": text })),
+            _ => value.to_json(),
+        }
+    }
+
+    fn write_recording(&mut self, entry: serde_json::Value) -> Result<(), EngineError> {
+        if let Some(recording) = &mut self.recording {
+            let mut write = || -> std::io::Result<()> {
+                serde_json::to_writer(&mut recording.out, &entry)?;
+                writeln!(recording.out)?;
+                recording.out.flush()
+            };
+            write().map_err(EngineError::RecordingFailed)?;
+            recording.statements += 1;
+        }
+        Ok(())
+    }
+
+    /// Add the configured hints to a query.
+    fn with_hints(&self, query: &str) -> String {
+        let hints = self
+            .result_cache_hint
+            .into_iter()
+            .chain(Some(self.query_hints.as_str()).filter(|h| !h.is_empty()))
+            .collect::<Vec<_>>();
+        if hints.is_empty() {
+            query.to_string()
+        } else {
+            inject_hint(query, &hints.join(" "))
+        }
+    }
+}
+
+/// Driver tuning applied to connections and the statements prepared on them.
+#[derive(Default, Clone)]
+struct ConnectionSettings {
+    /// The number of prepared statements to cache per connection, keyed by
+    /// SQL text, or `None` for the driver's default. Zero disables the cache.
+    stmt_cache_size: Option<u32>,
+    /// The number of rows fetched ahead with each query, or `None` for the
+    /// driver's default.
+    prefetch_rows: Option<u32>,
+    /// Whether each statement is committed as soon as it executes.
+    autocommit: bool,
+    /// How long a single round trip to the database may take before it is
+    /// cancelled, or `None` to wait indefinitely.
+    call_timeout: Option<Duration>,
+}
+
+impl ConnectionSettings {
+    /// Open a new connection with these settings.
+    fn connect(
+        &self,
+        username: &str,
+        password: &str,
+        connect_string: &str,
+    ) -> oracle::Result<Connection> {
+        let mut connector = Connector::new(username, password, connect_string);
+        if let Some(size) = self.stmt_cache_size {
+            connector.stmt_cache_size(size);
+        }
+        let mut conn = connector.connect()?;
+        conn.set_autocommit(self.autocommit);
+        conn.set_call_timeout(self.call_timeout)?;
+        Ok(conn)
+    }
+
+    /// Apply the settings that can be changed on an open connection.
+    fn apply(&self, conn: &mut Connection) -> oracle::Result<()> {
+        conn.set_stmt_cache_size(self.stmt_cache_size.unwrap_or(DEFAULT_STMT_CACHE_SIZE))?;
+        conn.set_autocommit(self.autocommit);
+        conn.set_call_timeout(self.call_timeout)?;
+        Ok(())
+    }
+}
+
+/// A connection set aside while another is active.
+struct NamedConnection {
+    /// `None` until the connection is first used.
+    conn: Option<Connection>,
+    credentials: Credentials,
+    last_used: Option<Instant>,
+    uncommitted: bool,
+}
+
+#[derive(Clone, Deserialize)]
+struct Credentials {
+    username: String,
+    password: String,
+    connect_string: String,
+}
+
+impl Credentials {
+    /// Open a new connection with these credentials.
+    fn connect(&self, settings: &ConnectionSettings) -> oracle::Result<Connection> {
+        settings.connect(&self.username, &self.password, &self.connect_string)
+    }
+}
+
+/// The environment variable that can point to an engine configuration file.
+const CONFIG_PATH_ENV: &str = "TESTANGEL_ORACLE_CONFIG";
+
+/// Defaults for the engine, read from the JSON file named by
+/// [`CONFIG_PATH_ENV`] so they can be standardised without editing flows.
+/// As with the matching instructions, zero for a size or limit means the
+/// default.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct EngineConfig {
+    /// Allow dangerous queries even when an instruction doesn't.
+    allow_dangerous_queries: bool,
+    stmt_cache_size: Option<u32>,
+    prefetch_rows: Option<u32>,
+    autocommit: bool,
+    /// The call timeout, in seconds.
+    call_timeout: Option<u64>,
+    max_rows: Option<usize>,
+    /// The memory budget, in megabytes.
+    memory_budget_mb: Option<usize>,
+    /// The most statements run at once on additional connections.
+    max_concurrent_statements: Option<usize>,
+    evidence: EvidenceVerbosity,
+    /// Named sets of credentials that can be connected to by name.
+    profiles: HashMap<String, Credentials>,
+}
+
+impl EngineConfig {
+    /// Read the configuration file, or use the defaults if none is set.
+    fn load() -> Result<Self, String> {
+        let Ok(path) = std::env::var(CONFIG_PATH_ENV) else {
+            return Ok(Self::default());
+        };
+        let contents = std::fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+        serde_json::from_str(&contents).map_err(|e| format!("{path}: {e}"))
+    }
+}
+
+/// How much evidence the query instructions record.
+#[derive(Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum EvidenceVerbosity {
+    /// Record every query that is run.
+    #[default]
+    Full,
+    /// Only record evidence that carries results.
+    Quiet,
+}
+
+/// Counters describing the engine's database work.
+#[derive(Default)]
+struct Metrics {
+    statements: u64,
+    errors: u64,
+    reconnects: u64,
+    rows_fetched: u64,
+    bytes_fetched: u64,
+    statement_time: Duration,
+}
+
+impl Metrics {
+    /// Record a statement having been executed.
+    fn record(&mut self, elapsed: Duration, succeeded: bool) {
+        self.statements += 1;
+        self.statement_time += elapsed;
+        if !succeeded {
+            self.errors += 1;
+        }
+    }
+
+    /// Each metric's name, description and value.
+    fn values(&self) -> [(&'static str, &'static str, f64); 6] {
+        [
+            (
+                "statements_total",
+                "Statements executed.",
+                self.statements as f64,
+            ),
+            (
+                "errors_total",
+                "Statements that failed.",
+                self.errors as f64,
+            ),
+            (
+                "reconnects_total",
+                "Dead connections replaced.",
+                self.reconnects as f64,
+            ),
+            (
+                "rows_fetched_total",
+                "Rows fetched.",
+                self.rows_fetched as f64,
+            ),
+            (
+                "bytes_fetched_total",
+                "Approximate bytes of results fetched.",
+                self.bytes_fetched as f64,
+            ),
+            (
+                "statement_seconds_total",
+                "Time spent executing statements.",
+                self.statement_time.as_secs_f64(),
+            ),
+        ]
+    }
+
+    /// Format the metrics in the Prometheus text exposition format.
+    fn to_prometheus(&self) -> String {
+        self.values()
+            .iter()
+            .map(|(name, help, value)| {
+                format!(
+                    "# HELP testangel_oracle_{name} {help}\n\
+                    # TYPE testangel_oracle_{name} counter\n\
+                    testangel_oracle_{name} {value}\n"
+                )
+            })
+            .collect()
+    }
+
+    /// Format the metrics as a JSON object.
+    fn to_json(&self) -> serde_json::Value {
+        self.values()
+            .iter()
+            .map(|(name, _, value)| (name.to_string(), json!(value)))
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+}
+
+/// A counting semaphore limiting how many statements run at once.
+struct Semaphore {
+    limit: usize,
+    running: Mutex<usize>,
+    released: Condvar,
+}
+
+/// A running statement's place in a [`Semaphore`], given up when dropped.
+struct SemaphorePermit<'a>(&'a Semaphore);
+
+impl Semaphore {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            running: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait until fewer than the limit of statements are running, then take a
+    /// place.
+    fn acquire(&self) -> SemaphorePermit<'_> {
+        let mut running = self.running.lock().unwrap_or_else(|e| e.into_inner());
+        while *running >= self.limit {
+            running = self
+                .released
+                .wait(running)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        *running += 1;
+        SemaphorePermit(self)
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        let mut running = self.0.running.lock().unwrap_or_else(|e| e.into_inner());
+        *running -= 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// A query running on its own connection in another thread.
+struct BackgroundQuery {
+    query: String,
+    thread: JoinHandle<oracle::Result<BackgroundResult>>,
+}
+
+/// What a background query returned.
+struct BackgroundResult {
+    /// The number of rows fetched.
+    rows: usize,
+    /// The first column of the first row.
+    first: String,
+    /// Whether fetching stopped early because of the row limit.
+    truncated: bool,
 }
 
+/// A file executed statements are written to, one JSON object per line.
+struct Recording {
+    path: String,
+    out: BufWriter<File>,
+    statements: usize,
+}
+
+/// A statement read back from a SQL recording.
+#[derive(Deserialize)]
+struct RecordedStatement {
+    statement: String,
+    #[serde(default)]
+    params: Vec<serde_json::Value>,
+    /// Rows of parameters, if the statement was run as a batch.
+    batch: Option<Vec<Vec<serde_json::Value>>>,
+}
+
+/// Execute a statement once for each row of parameters, sending the rows in
+/// batches, and return the number of rows affected.
+fn execute_batch(
+    conn: &Connection,
+    statement: &str,
+    rows: &[Vec<SqlValue>],
+) -> oracle::Result<u64> {
+    let mut rows_affected = 0;
+    for chunk in rows.chunks(MAX_BATCH_SIZE) {
+        let mut batch = conn
+            .batch(statement, chunk.len())
+            .with_row_counts()
+            .build()?;
+        for row in chunk {
+            let sql_params: Vec<&dyn ToSql> = row.iter().map(SqlValue::as_to_sql).collect();
+            batch.append_row(sql_params.as_slice())?;
+        }
+        batch.execute()?;
+        rows_affected += batch.row_counts()?.iter().sum::<u64>();
+    }
+    Ok(rows_affected)
+}
+
+/// Run a recorded statement, returning a description of what it did. Queries
+/// stop fetching after `max_rows` rows, if set.
+fn replay_statement(
+    conn: &Connection,
+    statement: &str,
+    params: &[SqlValue],
+    batch: Option<&[Vec<SqlValue>]>,
+    max_rows: Option<usize>,
+) -> oracle::Result<String> {
+    if let Some(rows) = batch {
+        let rows_affected = execute_batch(conn, statement, rows)?;
+        return Ok(format!(
+            "{} row(s) of parameters, affecting {rows_affected} row(s)",
+            rows.len()
+        ));
+    }
+
+    let sql_params: Vec<&dyn ToSql> = params.iter().map(SqlValue::as_to_sql).collect();
+    let mut stmt = conn.statement(statement).build()?;
+    if stmt.is_query() {
+        let mut rows = 0;
+        for row in stmt.query(sql_params.as_slice())? {
+            if max_rows.is_some_and(|max| rows >= max) {
+                return Ok(format!(
+                    "{rows} row(s) fetched before the row limit was reached"
+                ));
+            }
+            row?;
+            rows += 1;
+        }
+        Ok(format!("{rows} row(s) fetched"))
+    } else {
+        stmt.execute(sql_params.as_slice())?;
+        Ok(format!("{} row(s) affected", stmt.row_count()?))
+    }
+}
+
+/// The tables, columns, indexes and constraints of a schema, each keyed by a
+/// name that is the same between databases and described by its definition.
+/// Indexes and constraints are keyed by their table and columns, since
+/// system-generated names differ between databases. Checks that a column is
+/// not null are left out, as the column's definition already covers them.
+type SchemaMetadata = BTreeMap<&'static str, BTreeMap<String, String>>;
+
+/// Read the metadata of a schema to compare, or the current schema if empty.
+fn schema_metadata(conn: &Connection, schema: &str) -> oracle::Result<SchemaMetadata> {
+    const QUERIES: [(&str, &str); 4] = [
+        (
+            "tables",
+            "SELECT table_name, DECODE(partitioned, 'YES', 'PARTITIONED') FROM all_tables \
+                WHERE owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND nested = 'NO' AND dropped = 'NO'",
+        ),
+        (
+            "columns",
+            "SELECT table_name || '.' || column_name, data_type || CASE \
+                    WHEN data_precision IS NOT NULL THEN '(' || data_precision || ',' || data_scale || ')' \
+                    WHEN char_used IS NOT NULL THEN '(' || char_length || DECODE(char_used, 'C', ' CHAR') || ')' \
+                    WHEN data_type = 'RAW' THEN '(' || data_length || ')' \
+                END || DECODE(nullable, 'N', ' NOT NULL') \
+                FROM all_tab_columns WHERE owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) \
+                AND table_name NOT LIKE 'BIN$%'",
+        ),
+        (
+            "indexes",
+            "SELECT i.table_name || ' (' || LISTAGG(c.column_name, ', ') WITHIN GROUP (ORDER BY c.column_position) || ')', \
+                    MAX(i.uniqueness) \
+                FROM all_indexes i JOIN all_ind_columns c ON c.index_owner = i.owner AND c.index_name = i.index_name \
+                WHERE i.owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND i.table_name NOT LIKE 'BIN$%' \
+                GROUP BY i.owner, i.index_name, i.table_name",
+        ),
+        (
+            "constraints",
+            "SELECT c.table_name || ' ' || c.constraint_type || ' (' || LISTAGG(cc.column_name, ', ') WITHIN GROUP (ORDER BY cc.position) || ')' \
+                    || CASE c.constraint_type WHEN 'C' THEN ' CHECK ' || c.search_condition_vc WHEN 'R' THEN ' REFERENCES ' || r.table_name END, \
+                    c.status || CASE c.constraint_type WHEN 'R' THEN ' ON DELETE ' || c.delete_rule END \
+                FROM all_constraints c JOIN all_cons_columns cc ON cc.owner = c.owner AND cc.constraint_name = c.constraint_name \
+                LEFT JOIN all_constraints r ON r.owner = c.r_owner AND r.constraint_name = c.r_constraint_name \
+                WHERE c.owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND c.constraint_type IN ('P', 'U', 'R', 'C') \
+                AND c.table_name NOT LIKE 'BIN$%' \
+                AND NOT (c.constraint_type = 'C' AND c.search_condition_vc = '\"' || cc.column_name || '\" IS NOT NULL') \
+                GROUP BY c.owner, c.constraint_name, c.table_name, c.constraint_type, c.search_condition_vc, r.table_name, c.status, c.delete_rule",
+        ),
+    ];
+
+    let mut metadata = SchemaMetadata::new();
+    for (kind, sql) in QUERIES {
+        let objects = metadata.entry(kind).or_default();
+        let mut rows = conn
+            .query_as::<(String, Option<String>)>(sql, &[&schema])?
+            .collect::<oracle::Result<Vec<_>>>()?;
+        // Sort so that objects sharing a name are numbered the same way in
+        // every database, rather than one silently replacing another
+        rows.sort();
+        for (name, definition) in rows {
+            let mut key = name.clone();
+            let mut n = 1;
+            while objects.contains_key(&key) {
+                n += 1;
+                key = format!("{name} [{n}]");
+            }
+            objects.insert(key, definition.unwrap_or_default());
+        }
+    }
+    Ok(metadata)
+}
+
+/// Compare the metadata of two schemas, returning a report of the objects
+/// only in each and those defined differently, and the number of differences.
+fn compare_schema_metadata(a: &SchemaMetadata, b: &SchemaMetadata) -> (serde_json::Value, usize) {
+    let mut report = serde_json::Map::new();
+    let mut differences = 0;
+    for (kind, objects_a) in a {
+        let objects_b = &b[kind];
+        let only_in_a = objects_a
+            .keys()
+            .filter(|name| !objects_b.contains_key(*name))
+            .collect::<Vec<_>>();
+        let only_in_b = objects_b
+            .keys()
+            .filter(|name| !objects_a.contains_key(*name))
+            .collect::<Vec<_>>();
+        let different = objects_a
+            .iter()
+            .filter_map(|(name, def_a)| {
+                let def_b = objects_b.get(name)?;
+                (def_a != def_b).then(|| json!({ "name": name, "a": def_a, "b": def_b }))
+            })
+            .collect::<Vec<_>>();
+        differences += only_in_a.len() + only_in_b.len() + different.len();
+        report.insert(
+            kind.to_string(),
+            json!({ "only_in_a": only_in_a, "only_in_b": only_in_b, "different": different }),
+        );
+    }
+    (serde_json::Value::Object(report), differences)
+}
+
+/// A query result kept for reuse.
 #[derive(Clone)]
+enum CachedResult {
+    String(String),
+    Integer(i32),
+}
+
+impl CachedResult {
+    /// The approximate number of bytes this result occupies.
+    fn approx_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + match self {
+                CachedResult::String(s) => s.len(),
+                CachedResult::Integer(_) => 0,
+            }
+    }
+}
+
+#[derive(Clone, Debug)]
 enum SqlValue {
     String(String),
+    NChar(NChar),
     Integer(i64),
+    Decimal(f64),
     Boolean(bool),
+    Null,
+}
+
+/// Text bound as NVARCHAR2, so characters outside the database character set
+/// survive the trip to NCHAR and NVARCHAR2 columns.
+#[derive(Clone, Debug)]
+struct NChar(String);
+
+impl ToSql for NChar {
+    fn oratype(&self, _conn: &Connection) -> oracle::Result<OracleType> {
+        Ok(OracleType::NVarchar2(self.0.chars().count().max(1) as u32))
+    }
+
+    fn to_sql(&self, val: &mut oracle::SqlValue) -> oracle::Result<()> {
+        self.0.to_sql(val)
+    }
+}
+
+/// A null to bind in place of [`SqlValue::Null`].
+static NULL: Option<String> = None;
+
+impl SqlValue {
+    /// The approximate number of bytes this value occupies.
+    fn approx_size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + match self {
+                SqlValue::String(s) | SqlValue::NChar(NChar(s)) => s.len(),
+                _ => 0,
+            }
+    }
+
+    /// Convert to JSON for a SQL recording, marking national strings so they
+    /// are bound the same way when replayed.
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            SqlValue::String(s) => json!(s),
+            SqlValue::NChar(NChar(s)) => json!({ "nchar": s }),
+            SqlValue::Integer(i) => json!(i),
+            SqlValue::Decimal(d) => json!(d),
+            SqlValue::Boolean(b) => json!(b),
+            SqlValue::Null => serde_json::Value::Null,
+        }
+    }
+
+    /// Replace `${env:NAME}` references in a string value, as when it was
+    /// first added.
+    fn with_env_refs_resolved(self) -> Result<Self, EngineError> {
+        Ok(match self {
+            SqlValue::String(s) => SqlValue::String(resolve_env_refs(&s)?),
+            SqlValue::NChar(NChar(s)) => SqlValue::NChar(NChar(resolve_env_refs(&s)?)),
+            value => value,
+        })
+    }
+
+    fn as_to_sql(&self) -> &dyn ToSql {
+        match self {
+            SqlValue::String(s) => s,
+            SqlValue::NChar(s) => s,
+            SqlValue::Integer(i) => i,
+            SqlValue::Decimal(d) => d,
+            SqlValue::Boolean(b) => b,
+            SqlValue::Null => &NULL,
+        }
+    }
+}
+
+impl TryFrom<&serde_json::Value> for SqlValue {
+    type Error = EngineError;
+
+    fn try_from(value: &serde_json::Value) -> Result<Self, Self::Error> {
+        use serde_json::Value;
+        Ok(match value {
+            Value::Null => SqlValue::Null,
+            Value::Bool(b) => SqlValue::Boolean(*b),
+            Value::Number(n) => match n.as_i64() {
+                Some(i) => SqlValue::Integer(i),
+                None => SqlValue::Decimal(n.as_f64().unwrap_or_default()),
+            },
+            Value::String(s) => SqlValue::String(s.clone()),
+            Value::Object(o) if o.len() == 1 && o.get("nchar").is_some_and(Value::is_string) => {
+                SqlValue::NChar(NChar(o["nchar"].as_str().unwrap_or_default().to_string()))
+            }
+            Value::Array(_) | Value::Object(_) => {
+                return Err(EngineError::InvalidBatchValue(value.to_string()))
+            }
+        })
+    }
 }
 
 #[derive(Error, Debug)]
@@ -23,11 +949,672 @@ pub enum EngineError {
     #[error("The state was poisoned critically.")]
     PoisonedState,
     #[error("An oracle error occurred: {0}")]
-    Oracle(#[from] oracle::Error),
+    Oracle(oracle::Error),
     #[error("A dangerous query was submitted and allow dangerous wasn't enabled.")]
     DangerousQuery,
     #[error("A query was made but a connection doens't exist")]
     NotYetConnected,
+    #[error("The table {0} doesn't exist or isn't visible to this user.")]
+    TableNotFound(String),
+    #[error("The constraint {0} doesn't exist.")]
+    ConstraintMissing(String),
+    #[error("The constraint {0} is disabled.")]
+    ConstraintDisabled(String),
+    #[error("{0} isn't a valid Oracle identifier.")]
+    InvalidIdentifier(String),
+    #[error("The synonym {0} is part of a looping chain of synonyms.")]
+    SynonymLoop(String),
+    #[error("The tablespace {0} doesn't exist or its usage isn't visible to this user.")]
+    TablespaceNotFound(String),
+    #[error("The tablespace {0} is {1:.1}% full, which exceeds the limit of {2:.1}%.")]
+    TablespaceThresholdExceeded(String, f64, f64),
+    #[error("A session can only be killed if you confirm you understand the consequences.")]
+    KillNotConfirmed,
+    #[error("Timed out waiting for locks on {0} to be released.")]
+    LockWaitTimedOut(String),
+    #[error("Timed out waiting for a change to {0}.")]
+    TableChangeTimedOut(String),
+    #[error("A background query with the handle {0} is already running.")]
+    BackgroundQueryExists(String),
+    #[error("There is no background query with the handle {0}.")]
+    UnknownBackgroundQuery(String),
+    #[error("The background query with the handle {0} crashed.")]
+    BackgroundQueryPanicked(String),
+    #[error("A load run needs either a duration or a number of iterations.")]
+    UnboundedLoad,
+    #[error("A load run can't use {0} connections when at most {1} statements may run at once.")]
+    ConcurrencyLimitExceeded(i32, usize),
+    #[error("A connection generating load crashed.")]
+    LoadWorkerPanicked,
+    #[error("Holding about {0} bytes would exceed the engine's memory budget of {1} bytes. Use the export instructions to stream large results to a file instead.")]
+    ResultTooLarge(usize, usize),
+    #[error("{0} isn't a metrics format. Use PROMETHEUS or JSON.")]
+    InvalidMetricsFormat(String),
+    #[error("The environment variable {0} isn't set.")]
+    MissingEnvVar(String),
+    #[error("The engine configuration file couldn't be read: {0}")]
+    InvalidConfig(String),
+    #[error("There is no connection profile named {0} in the engine configuration.")]
+    UnknownProfile(String),
+    #[error("The Oracle Client libraries couldn't be loaded. Install Oracle Instant Client, or set {} to the directory containing it: {0}", CLIENT_LIB_DIR_ENV)]
+    ClientLibraries(oracle::Error),
+    #[error("{0} isn't a valid result cache mode. Use RESULT_CACHE, NO_RESULT_CACHE or leave it empty for the default.")]
+    InvalidResultCacheMode(String),
+    #[error("The batch rows must be a JSON array of arrays of values.")]
+    InvalidBatchRows,
+    #[error("{0} can't be used as a batch value.")]
+    InvalidBatchValue(String),
+    #[error("The hints {0} can't contain the end of a comment.")]
+    InvalidHints(String),
+    #[error("The Oracle Client libraries have already been loaded, so their directory can't be changed. Set the directory before connecting.")]
+    ClientAlreadyLoaded,
+    #[error("{0} isn't a queue payload type. Use RAW or JSON.")]
+    InvalidPayloadType(String),
+    #[error("No message arrived on the queue {0} before the timeout.")]
+    DequeueTimedOut(String),
+    #[error("{0} isn't a DBMS_CRYPTO algorithm such as AES256/CBC/PKCS5.")]
+    InvalidCryptoAlgorithm(String),
+    #[error("{0} isn't a hash algorithm. Use MD5, SHA1, SHA256, SHA384 or SHA512.")]
+    InvalidHashAlgorithm(String),
+    #[error("The SQL recording couldn't be written: {0}")]
+    RecordingFailed(std::io::Error),
+    #[error("The SQL recording couldn't be read: {0}")]
+    InvalidRecording(String),
+    #[error("No query has returned a result to use as a parameter yet.")]
+    NoLastResult,
+    #[error("No variable named {0} has been set.")]
+    UnknownVariable(String),
+    #[error("The unique constraint {0} was violated: a row with the same key already exists.")]
+    UniqueConstraintViolated(String),
+    #[error("{0} isn't a column or other name the statement can see.")]
+    UnknownIdentifier(String),
+    #[error("A table or view in the statement doesn't exist or isn't visible to this user.")]
+    TableOrViewNotFound,
+    #[error("The user doesn't have the privileges the statement needs.")]
+    InsufficientPrivileges,
+    #[error("The connection died after being idle for {0}s while it had uncommitted changes, which have been lost. The next statement will reconnect.")]
+    ConnectionLost(u64),
+    #[error("There is no connection named {0}.")]
+    UnknownConnection(String),
+    #[error("Committing {0} failed after {1} had committed, so the rest were rolled back: {2}")]
+    CommitAllFailed(String, String, oracle::Error),
+}
+
+impl From<oracle::Error> for EngineError {
+    /// Turn frequent database errors into variants with actionable messages,
+    /// naming the object involved where the database does.
+    fn from(err: oracle::Error) -> Self {
+        if let oracle::Error::OciError(db_error) = &err {
+            let message = db_error.message();
+            match db_error.code() {
+                ORA_UNIQUE_CONSTRAINT => {
+                    if let Some(name) = parenthesised(message) {
+                        return EngineError::UniqueConstraintViolated(name.to_string());
+                    }
+                }
+                ORA_INVALID_IDENTIFIER => {
+                    if let Some(name) = quoted_name(message) {
+                        return EngineError::UnknownIdentifier(name);
+                    }
+                }
+                ORA_TABLE_NOT_FOUND => {
+                    return match quoted_name(message) {
+                        Some(name) => EngineError::TableNotFound(name),
+                        None => EngineError::TableOrViewNotFound,
+                    };
+                }
+                ORA_INSUFFICIENT_PRIVILEGES => return EngineError::InsufficientPrivileges,
+                _ => (),
+            }
+        }
+        EngineError::Oracle(err)
+    }
+}
+
+/// The text in the first pair of parentheses in a message.
+fn parenthesised(message: &str) -> Option<&str> {
+    let start = message.find('(')? + 1;
+    let len = message[start..].find(')')?;
+    Some(&message[start..start + len])
+}
+
+/// The possibly schema-qualified name quoted in a message, e.g. `HR.EMPLOYEES`
+/// from `"HR"."EMPLOYEES"`.
+fn quoted_name(message: &str) -> Option<String> {
+    let start = message.find('"')?;
+    let end = message.rfind('"')?;
+    (start < end).then(|| message[start..end].replace('"', ""))
+}
+
+/// The environment variable that can point to the Oracle Client libraries.
+const CLIENT_LIB_DIR_ENV: &str = "TESTANGEL_ORACLE_CLIENT_LIB_DIR";
+
+/// The name of the connection opened by the connect instructions until
+/// another is used.
+const DEFAULT_CONNECTION: &str = "default";
+/// The encoding the driver always uses for text sent to and from the database.
+const CLIENT_ENCODING: &str = "UTF-8";
+
+/// Load the Oracle Client libraries, from `lib_dir` or the directory set in
+/// [`CLIENT_LIB_DIR_ENV`] if given, otherwise from the platform's usual
+/// search path. Returns `false` if they had already been loaded.
+fn load_client_libraries(lib_dir: Option<String>) -> oracle::Result<bool> {
+    let mut init_params = InitParams::new();
+    if let Some(dir) = lib_dir.or_else(|| std::env::var(CLIENT_LIB_DIR_ENV).ok()) {
+        init_params.oracle_client_lib_dir(dir)?;
+    }
+    init_params.init()
+}
+
+/// Words that make a query dangerous unless dangerous queries are allowed.
+const DANGEROUS_WORDS: [&str; 3] = ["truncate", "delete", "drop"];
+/// The algorithms STANDARD_HASH supports.
+const HASH_ALGORITHMS: [&str; 5] = ["MD5", "SHA1", "SHA256", "SHA384", "SHA512"];
+/// The maximum number of synonyms that will be followed when resolving a name.
+const MAX_SYNONYM_DEPTH: usize = 32;
+/// The most parameter rows sent to the database in one batch.
+const MAX_BATCH_SIZE: usize = 1000;
+/// The number of rows fetched from the database at a time when exporting.
+const EXPORT_FETCH_ARRAY_SIZE: u32 = 1000;
+/// The number of prepared statements the driver caches per connection unless
+/// told otherwise.
+const DEFAULT_STMT_CACHE_SIZE: u32 = 20;
+/// The default memory budget, in megabytes.
+const DEFAULT_MEMORY_BUDGET_MB: usize = 256;
+/// How long a connection can be idle before it is checked before use.
+const IDLE_PING_INTERVAL: Duration = Duration::from_secs(60);
+/// How often to check whether locks have been released while waiting.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How often to check whether a table has changed while waiting.
+const CHANGE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// The error raised when a unique constraint is violated.
+const ORA_UNIQUE_CONSTRAINT: i32 = 1;
+/// The error raised when a statement names a column that doesn't exist.
+const ORA_INVALID_IDENTIFIER: i32 = 904;
+/// The error raised when a statement names a table or view that doesn't exist.
+const ORA_TABLE_NOT_FOUND: i32 = 942;
+/// The error raised when the user lacks a privilege a statement needs.
+const ORA_INSUFFICIENT_PRIVILEGES: i32 = 1031;
+/// The error raised when a dequeue times out without a message.
+const ORA_DEQUEUE_TIMEOUT: i32 = 25228;
+
+/// Reject a query containing dangerous words unless they have been allowed.
+fn check_dangerous(query: &str, danger_allowed: bool) -> Result<(), EngineError> {
+    if !danger_allowed {
+        for word in query.split(' ') {
+            let word = word.trim();
+            if DANGEROUS_WORDS.contains(&word.to_ascii_lowercase().as_str()) {
+                return Err(EngineError::DangerousQuery);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Insert an optimizer hint comment after the first keyword of a statement,
+/// skipping any leading comments. For a query starting with a WITH clause the
+/// hint goes after the SELECT of the main query, where Oracle reads it.
+fn inject_hint(query: &str, hint: &str) -> String {
+    let query = query.trim_start();
+    let mut words = sql_words(query);
+    let Some((keyword_start, keyword_end)) = words.next() else {
+        return query.to_string();
+    };
+    let mut at = keyword_end;
+    if query[keyword_start..keyword_end].eq_ignore_ascii_case("with") {
+        if let Some((_, end)) =
+            words.find(|&(start, end)| query[start..end].eq_ignore_ascii_case("select"))
+        {
+            at = end;
+        }
+    }
+    let (before, after) = query.split_at(at);
+    format!("{before} /*+ {hint} */{after}")
+}
+
+/// The byte ranges of the words of a statement outside comments, string
+/// literals, quoted identifiers and parentheses.
+fn sql_words(sql: &str) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let bytes = sql.as_bytes();
+    let mut i = 0;
+    let mut depth = 0usize;
+    std::iter::from_fn(move || {
+        while i < bytes.len() {
+            let rest = &sql[i..];
+            if rest.starts_with("--") {
+                i += rest.find('\n').map_or(rest.len(), |n| n + 1);
+            } else if rest.starts_with("/*") {
+                i += rest[2..].find("*/").map_or(rest.len(), |n| n + 4);
+            } else if bytes[i] == b'\'' || bytes[i] == b'"' {
+                let quote = bytes[i] as char;
+                i += rest[1..].find(quote).map_or(rest.len(), |n| n + 2);
+            } else if bytes[i] == b'(' {
+                depth += 1;
+                i += 1;
+            } else if bytes[i] == b')' {
+                depth = depth.saturating_sub(1);
+                i += 1;
+            } else if bytes[i].is_ascii_alphabetic() {
+                let start = i;
+                while i < bytes.len()
+                    && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'_' | b'$' | b'#'))
+                {
+                    i += 1;
+                }
+                if depth == 0 {
+                    return Some((start, i));
+                }
+            } else {
+                i += rest.chars().next().map_or(1, char::len_utf8);
+            }
+        }
+        None
+    })
+}
+
+/// Replace each `${env:NAME}` in a value with the environment variable `NAME`,
+/// so that secrets and environment-specific values can be kept out of flows.
+fn resolve_env_refs(value: &str) -> Result<String, EngineError> {
+    let mut resolved = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${env:") {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        let name = &rest[start + "${env:".len()..start + len];
+        let var = std::env::var(name).map_err(|_| EngineError::MissingEnvVar(name.to_string()))?;
+        resolved.push_str(&rest[..start]);
+        resolved.push_str(&var);
+        rest = &rest[start + len + 1..];
+    }
+    resolved.push_str(rest);
+    Ok(resolved)
+}
+
+/// Split an optionally schema-qualified object name (e.g. `HR.EMPLOYEES`) into
+/// its owner and object name, uppercased to match the data dictionary.
+fn split_object_name(name: &str) -> (Option<String>, String) {
+    match name.trim().split_once('.') {
+        Some((owner, object)) => (
+            Some(owner.trim().to_ascii_uppercase()),
+            object.trim().to_ascii_uppercase(),
+        ),
+        None => (None, name.trim().to_ascii_uppercase()),
+    }
+}
+
+/// Quote an optionally schema-qualified object name for use in statements that
+/// can't take binds (such as DDL), rejecting anything that isn't a plain
+/// identifier.
+fn quote_object_name(name: &str) -> Result<String, EngineError> {
+    let is_identifier = |id: &str| {
+        !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '#'))
+    };
+    match split_object_name(name) {
+        (Some(owner), object) if is_identifier(&owner) && is_identifier(&object) => {
+            Ok(format!("\"{owner}\".\"{object}\""))
+        }
+        (None, object) if is_identifier(&object) => Ok(format!("\"{object}\"")),
+        _ => Err(EngineError::InvalidIdentifier(name.to_string())),
+    }
+}
+
+/// Convert an algorithm such as `AES256/CBC/PKCS5` to the DBMS_CRYPTO
+/// expression combining its cipher, chaining and padding.
+fn crypto_type(algorithm: &str) -> Result<String, EngineError> {
+    const CIPHERS: [&str; 6] = ["DES", "3DES_2KEY", "3DES", "AES128", "AES192", "AES256"];
+    const CHAINING: [&str; 4] = ["CBC", "CFB", "ECB", "OFB"];
+    const PADDING: [&str; 3] = ["PKCS5", "NONE", "ZERO"];
+
+    let upper = algorithm.trim().to_ascii_uppercase();
+    match upper.split('/').collect::<Vec<_>>().as_slice() {
+        [cipher, chain, pad]
+            if CIPHERS.contains(cipher) && CHAINING.contains(chain) && PADDING.contains(pad) =>
+        {
+            Ok(format!(
+                "DBMS_CRYPTO.ENCRYPT_{cipher} + DBMS_CRYPTO.CHAIN_{chain} + DBMS_CRYPTO.PAD_{pad}"
+            ))
+        }
+        _ => Err(EngineError::InvalidCryptoAlgorithm(algorithm.to_string())),
+    }
+}
+
+/// A synonym chain resolved to its final target.
+struct ResolvedSynonym {
+    /// Each synonym followed, in order, as `OWNER.NAME`.
+    chain: Vec<String>,
+    owner: String,
+    name: String,
+    db_link: Option<String>,
+}
+
+/// Follow a chain of synonyms to the object it finally refers to, using the
+/// same private-then-public lookup as Oracle for unqualified names.
+fn resolve_synonym(conn: &Connection, name: &str) -> Result<ResolvedSynonym, EngineError> {
+    let (owner, name) = split_object_name(name);
+    let unqualified = owner.is_none();
+    let mut owner = match owner {
+        Some(owner) => owner,
+        None => conn.query_row_as::<String>(
+            "SELECT SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA') FROM dual",
+            &[],
+        )?,
+    };
+    let mut resolved = ResolvedSynonym {
+        chain: vec![],
+        owner: owner.clone(),
+        name,
+        db_link: None,
+    };
+
+    let lookup = |owner: &str, name: &str| {
+        conn.query_as::<(Option<String>, String, Option<String>)>(
+            "SELECT table_owner, table_name, db_link FROM all_synonyms WHERE owner = :1 AND synonym_name = :2",
+            &[&owner, &name],
+        )?
+        .next()
+        .transpose()
+    };
+
+    let mut next = lookup(&owner, &resolved.name)?;
+    if next.is_none() && unqualified {
+        let exists = conn.query_row_as::<i64>(
+            "SELECT COUNT(*) FROM all_objects WHERE owner = :1 AND object_name = :2",
+            &[&owner, &resolved.name],
+        )? > 0;
+        if !exists {
+            next = lookup("PUBLIC", &resolved.name)?;
+            owner = "PUBLIC".to_string();
+        }
+    }
+
+    while let Some((target_owner, target_name, db_link)) = next {
+        if resolved.chain.len() >= MAX_SYNONYM_DEPTH {
+            return Err(EngineError::SynonymLoop(resolved.chain[0].clone()));
+        }
+        resolved.chain.push(format!("{owner}.{}", resolved.name));
+        owner = target_owner.unwrap_or(owner);
+        resolved.owner = owner.clone();
+        resolved.name = target_name;
+        if db_link.is_some() {
+            resolved.db_link = db_link;
+            break;
+        }
+        next = lookup(&owner, &resolved.name)?;
+    }
+
+    Ok(resolved)
+}
+
+/// Look up a constraint on a table, returning its type, status and validation
+/// state if it exists.
+fn find_constraint(
+    conn: &Connection,
+    table: &str,
+    constraint: &str,
+) -> oracle::Result<Option<(String, String, String)>> {
+    let (owner, table) = split_object_name(table);
+    let constraint = constraint.trim().to_ascii_uppercase();
+    let found = conn
+        .query_as::<(String, String, String)>(
+            "SELECT constraint_type, status, validated FROM all_constraints \
+                WHERE owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) \
+                AND table_name = :2 AND constraint_name = :3",
+            &[&owner, &table, &constraint],
+        )?
+        .next()
+        .transpose()?;
+    Ok(found.map(|(kind, status, validated)| {
+        let kind = match kind.as_str() {
+            "P" => "PRIMARY KEY",
+            "U" => "UNIQUE",
+            "R" => "FOREIGN KEY",
+            "C" => "CHECK",
+            "V" => "VIEW CHECK OPTION",
+            "O" => "READ ONLY",
+            _ => kind.as_str(),
+        }
+        .to_string();
+        (kind, status, validated)
+    }))
+}
+
+/// The formats query results can be exported in.
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Format an INTERVAL DAY TO SECOND as an ISO-8601 duration, e.g. `P1DT2H3M4.5S`.
+fn interval_ds_to_iso8601(interval: &IntervalDS) -> String {
+    let negative = interval.days() < 0
+        || interval.hours() < 0
+        || interval.minutes() < 0
+        || interval.seconds() < 0
+        || interval.nanoseconds() < 0;
+    let mut seconds = interval.seconds().abs().to_string();
+    if interval.nanoseconds() != 0 {
+        let fraction = format!("{:09}", interval.nanoseconds().abs());
+        seconds.push('.');
+        seconds.push_str(fraction.trim_end_matches('0'));
+    }
+    format!(
+        "{}P{}DT{}H{}M{seconds}S",
+        if negative { "-" } else { "" },
+        interval.days().abs(),
+        interval.hours().abs(),
+        interval.minutes().abs(),
+    )
+}
+
+/// Format an INTERVAL YEAR TO MONTH as an ISO-8601 duration, e.g. `P1Y2M`.
+fn interval_ym_to_iso8601(interval: &IntervalYM) -> String {
+    let negative = interval.years() < 0 || interval.months() < 0;
+    format!(
+        "{}P{}Y{}M",
+        if negative { "-" } else { "" },
+        interval.years().abs(),
+        interval.months().abs(),
+    )
+}
+
+/// The whole number of seconds in an INTERVAL DAY TO SECOND.
+fn interval_ds_seconds(interval: &IntervalDS) -> i64 {
+    interval.days() as i64 * 86_400
+        + interval.hours() as i64 * 3_600
+        + interval.minutes() as i64 * 60
+        + interval.seconds() as i64
+}
+
+/// The type of the column of a row with the given name, if there is one.
+fn column_type<'a>(row: &'a Row, column: &str) -> Option<&'a OracleType> {
+    row.column_info()
+        .iter()
+        .find(|c| c.name() == column)
+        .map(|c| c.oracle_type())
+}
+
+/// The approximate number of bytes a LOB column of a row will take as text,
+/// read from the LOB's length without fetching its contents, or zero for
+/// columns that aren't LOBs fetched as locators. Character LOBs are measured in
+/// characters, so text outside ASCII takes somewhat more.
+fn lob_text_size<I: ColumnIndex + Copy>(
+    row: &Row,
+    idx: I,
+    oracle_type: &OracleType,
+) -> oracle::Result<usize> {
+    let size = match oracle_type {
+        OracleType::CLOB => row.get::<I, Option<Clob>>(idx)?.map(|lob| lob.size()),
+        OracleType::NCLOB => row.get::<I, Option<NClob>>(idx)?.map(|lob| lob.size()),
+        // BLOBs are read as hexadecimal, so each byte takes two characters
+        OracleType::BLOB => row
+            .get::<I, Option<Blob>>(idx)?
+            .map(|lob| lob.size().map(|n| n * 2)),
+        _ => None,
+    };
+    Ok(size.transpose()?.unwrap_or_default() as usize)
+}
+
+/// Get a column of a row as text, formatting intervals as ISO-8601 durations.
+fn column_to_string<I: ColumnIndex + Copy>(
+    row: &Row,
+    idx: I,
+    oracle_type: &OracleType,
+) -> oracle::Result<Option<String>> {
+    Ok(match oracle_type {
+        OracleType::IntervalDS(..) => row
+            .get::<I, Option<IntervalDS>>(idx)?
+            .map(|i| interval_ds_to_iso8601(&i)),
+        OracleType::IntervalYM(_) => row
+            .get::<I, Option<IntervalYM>>(idx)?
+            .map(|i| interval_ym_to_iso8601(&i)),
+        _ => row.get::<I, Option<String>>(idx)?,
+    })
+}
+
+/// Convert a column of a row to JSON, keeping numbers and booleans as such.
+fn column_to_json(
+    row: &Row,
+    idx: usize,
+    oracle_type: &OracleType,
+) -> oracle::Result<serde_json::Value> {
+    Ok(match oracle_type {
+        // Unconstrained numbers (such as COUNT(*)) have a precision of 0
+        OracleType::Number(precision, scale) if *precision == 0 || *scale <= 0 => {
+            match row.get::<usize, Option<i64>>(idx) {
+                Ok(value) => json!(value),
+                Err(_) => match row.get::<usize, Option<String>>(idx)? {
+                    Some(value) => number_text_to_json(value),
+                    None => serde_json::Value::Null,
+                },
+            }
+        }
+        OracleType::Int64 | OracleType::UInt64 => match row.get::<usize, Option<i64>>(idx) {
+            Ok(value) => json!(value),
+            // Too large for an i64, so keep every digit as a string
+            Err(_) => json!(row.get::<usize, Option<String>>(idx)?),
+        },
+        OracleType::Number(..)
+        | OracleType::Float(_)
+        | OracleType::BinaryFloat
+        | OracleType::BinaryDouble => json!(row.get::<usize, Option<f64>>(idx)?),
+        OracleType::Boolean => json!(row.get::<usize, Option<bool>>(idx)?),
+        _ => json!(column_to_string(row, idx, oracle_type)?),
+    })
+}
+
+/// Convert the text of a number that didn't fit in an i64 to JSON, as a number
+/// if it is a fraction that an f64 holds exactly, otherwise as a string so no
+/// digits are lost.
+fn number_text_to_json(value: String) -> serde_json::Value {
+    match value.parse::<f64>() {
+        Ok(f) if f.fract() != 0.0 && f.to_string() == value => json!(f),
+        _ => json!(value),
+    }
+}
+
+/// Add evidence of the warning the last statement on a connection raised, such
+/// as a PL/SQL unit being created with compilation errors, and return its
+/// message, or an empty string if there was none.
+fn warning_evidence(conn: &Connection, evidence: &mut Vec<Evidence>) -> String {
+    match conn.last_warning() {
+        Some(warning) => {
+            evidence.push(Evidence {
+                label: "Statement Warning".to_string(),
+                content: EvidenceContent::Textual(warning.message().to_string()),
+            });
+            warning.message().to_string()
+        }
+        None => String::new(),
+    }
+}
+
+/// Stream the rows of a query to a file a row at a time, adding progress
+/// evidence every `progress_every` rows, and return the number of rows written.
+fn export_rows(
+    stmt: &mut Statement,
+    params: &[&dyn ToSql],
+    path: &str,
+    format: ExportFormat,
+    progress_every: usize,
+    evidence: &mut Vec<Evidence>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let rows = stmt.query(params).map_err(EngineError::from)?;
+    let columns = rows
+        .column_info()
+        .iter()
+        .map(|c| (c.name().to_string(), c.oracle_type().clone()))
+        .collect::<Vec<_>>();
+
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Csv => {
+            let header = columns.iter().map(|(name, _)| csv_field(name));
+            writeln!(out, "{}", header.collect::<Vec<_>>().join(","))?;
+        }
+        ExportFormat::Json => write!(out, "[")?,
+    }
+
+    let mut count = 0;
+    for row in rows {
+        let row = row.map_err(EngineError::from)?;
+        match format {
+            ExportFormat::Csv => {
+                let fields = columns
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (_, oracle_type))| {
+                        Ok(csv_field(
+                            &column_to_string(&row, i, oracle_type)?.unwrap_or_default(),
+                        ))
+                    })
+                    .collect::<oracle::Result<Vec<_>>>()
+                    .map_err(EngineError::from)?;
+                writeln!(out, "{}", fields.join(","))?;
+            }
+            ExportFormat::Json => {
+                let mut object = serde_json::Map::new();
+                for (i, (name, oracle_type)) in columns.iter().enumerate() {
+                    object.insert(
+                        name.clone(),
+                        column_to_json(&row, i, oracle_type).map_err(EngineError::from)?,
+                    );
+                }
+                if count > 0 {
+                    write!(out, ",")?;
+                }
+                writeln!(out)?;
+                serde_json::to_writer(&mut out, &object)?;
+            }
+        }
+        count += 1;
+
+        if progress_every > 0 && count % progress_every == 0 {
+            evidence.push(Evidence {
+                label: "Export Progress".to_string(),
+                content: EvidenceContent::Textual(format!("{count} row(s) written to {path}")),
+            });
+        }
+    }
+
+    if let ExportFormat::Json = format {
+        writeln!(out, "\n]")?;
+    }
+    out.flush()?;
+    Ok(count)
 }
 
 lazy_static! {
@@ -35,187 +1622,2463 @@ lazy_static! {
         Engine::new("Oracle SQL", "OracleDB", env!("CARGO_PKG_VERSION"))
         /* Connect */
         .with_instruction(
-            Instruction::new("oracle-connect", "Connect", "Connect", "Connect to an Oracle SQL server.")
+            Instruction::new("oracle-connect", "Connect", "Connect", "Connect to an Oracle SQL server. Values like ${env:NAME} are replaced with the environment variable NAME.")
                 .with_parameter("username", "Username", ParameterKind::String)
                 .with_parameter("password", "Password", ParameterKind::String)
                 .with_parameter("connect_string", "Connection String", ParameterKind::String),
             |state: &mut Mutex<State>, params, _output, _evidence| {
                 let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
 
-                let username = params["username"].value_string();
-                let password = params["password"].value_string();
-                let connect_string = params["connect_string"].value_string();
+                let username = resolve_env_refs(&params["username"].value_string())?;
+                let password = resolve_env_refs(&params["password"].value_string())?;
+                let connect_string = resolve_env_refs(&params["connect_string"].value_string())?;
 
-                state.conn = Some(Connection::connect(username, password, connect_string)?);
+                if let Some(e) = &state.config_error {
+                    return Err(Box::new(EngineError::InvalidConfig(e.clone())));
+                }
+                load_client_libraries(None).map_err(EngineError::ClientLibraries)?;
+                let credentials = Credentials { username, password, connect_string };
+                state.conn = Some(credentials.connect(&state.settings).map_err(EngineError::from)?);
+                state.last_used = Some(Instant::now());
+                state.credentials = Some(credentials);
+                state.forget_cached_results(&state.active_connection.clone());
+                state.uncommitted = false;
                 Ok(())
             }
         )
-
-        /* Add Parameters */
         .with_instruction(
-            Instruction::new("oracle-query-add-parameter-string", "AddQueryParameterString", "Add Query Parameter: String", "Add a parameter to be used later in a query.")
-                .with_parameter("sql_param", "Parameter Value", ParameterKind::String),
+            Instruction::new("oracle-connect-lazily", "ConnectLazily", "Connect Lazily", "Store the details to connect to an Oracle SQL server, but only open the connection when it is first used. Values like ${env:NAME} are replaced with the environment variable NAME.")
+                .with_parameter("username", "Username", ParameterKind::String)
+                .with_parameter("password", "Password", ParameterKind::String)
+                .with_parameter("connect_string", "Connection String", ParameterKind::String),
             |state: &mut Mutex<State>, params, _output, _evidence| {
                 let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
 
-                let sql_param = params["sql_param"].value_string();
+                let username = resolve_env_refs(&params["username"].value_string())?;
+                let password = resolve_env_refs(&params["password"].value_string())?;
+                let connect_string = resolve_env_refs(&params["connect_string"].value_string())?;
 
-                state.params.push(SqlValue::String(sql_param));
+                if let Some(e) = &state.config_error {
+                    return Err(Box::new(EngineError::InvalidConfig(e.clone())));
+                }
+                load_client_libraries(None).map_err(EngineError::ClientLibraries)?;
+                state.conn = None;
+                state.credentials = Some(Credentials { username, password, connect_string });
+                state.forget_cached_results(&state.active_connection.clone());
+                state.uncommitted = false;
                 Ok(())
             }
         )
         .with_instruction(
-            Instruction::new("oracle-query-add-parameter-integer", "AddQueryParameterInteger", "Add Query Parameter: Integer", "Add a parameter to be used later in a query.")
-                .with_parameter("sql_param", "Parameter Value", ParameterKind::Integer),
+            Instruction::new("oracle-connect-profile", "ConnectProfile", "Connect using Profile", "Connect to an Oracle SQL server using a connection profile from the engine configuration file.")
+                .with_parameter("profile", "Profile", ParameterKind::String),
             |state: &mut Mutex<State>, params, _output, _evidence| {
                 let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
 
-                let sql_param = params["sql_param"].value_i32();
+                let profile = params["profile"].value_string();
 
-                state.params.push(SqlValue::Integer(sql_param as i64));
+                if let Some(e) = &state.config_error {
+                    return Err(Box::new(EngineError::InvalidConfig(e.clone())));
+                }
+                let profile = state.config.profiles.get(&profile)
+                    .ok_or(EngineError::UnknownProfile(profile))?;
+                let credentials = Credentials {
+                    username: resolve_env_refs(&profile.username)?,
+                    password: resolve_env_refs(&profile.password)?,
+                    connect_string: resolve_env_refs(&profile.connect_string)?,
+                };
+                load_client_libraries(None).map_err(EngineError::ClientLibraries)?;
+                state.conn = Some(credentials.connect(&state.settings).map_err(EngineError::from)?);
+                state.last_used = Some(Instant::now());
+                state.credentials = Some(credentials);
+                state.forget_cached_results(&state.active_connection.clone());
+                state.uncommitted = false;
                 Ok(())
             }
         )
         .with_instruction(
-            Instruction::new("oracle-query-add-parameter-boolean", "AddQueryParameterBoolean", "Add Query Parameter: Boolean", "Add a parameter to be used later in a query.")
-                .with_parameter("sql_param", "Parameter Value", ParameterKind::Boolean),
-            |state: &mut Mutex<State>, params, _output, _evidence| {
-                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+            Instruction::new("oracle-set-client-library-directory", "SetClientLibraryDirectory", "Set Client Library Directory", "Load the Oracle Client libraries from a specific directory. This must be done before connecting.")
+                .with_parameter("directory", "Directory", ParameterKind::String),
+            |_state: &mut Mutex<State>, params, _output, _evidence| {
+                let directory = params["directory"].value_string();
 
-                let sql_param = params["sql_param"].value_bool();
-
-                state.params.push(SqlValue::Boolean(sql_param));
+                if !load_client_libraries(Some(directory)).map_err(EngineError::ClientLibraries)? {
+                    return Err(Box::new(EngineError::ClientAlreadyLoaded));
+                }
                 Ok(())
             }
         )
-
-        /* Run Query */
         .with_instruction(
-            Instruction::new("oracle-query", "ExecuteQuery", "Execute Query", "Execute a query. If the query contains dangerous words, you must allow dangerous queries.")
-                .with_parameter("query", "Query", ParameterKind::String)
-                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean),
-            |state: &mut Mutex<State>, params, _output, evidence| {
-                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
-
-                let danger_queries = ["truncate", "delete", "drop"];
-
-                let query = params["query"].value_string();
-                let danger_allowed = params["dangerous"].value_bool();
-
-                if !danger_allowed {
-                    for word in query.split(' ') {
-                        let word = word.trim();
-                        if danger_queries.contains(&word.to_ascii_lowercase().as_str()) {
-                            return Err(Box::new(EngineError::DangerousQuery));
-                        }
-                    }
-                }
-
-                let conn = state.conn.as_ref().ok_or(EngineError::NotYetConnected)?;
-                let sql_params_vec = state.params.clone();
-                state.params.clear();
+            Instruction::new("oracle-check-client-libraries", "CheckClientLibraries", "Check Client Libraries", "Check whether the Oracle Client libraries can be loaded, outputting their version or the reason they couldn't be loaded.")
+                .with_output("loaded", "Loaded", ParameterKind::Boolean)
+                .with_output("version", "Client Version", ParameterKind::String)
+                .with_output("error", "Error", ParameterKind::String),
+            |_state: &mut Mutex<State>, _params, output, evidence| {
+                let (version, error) = match load_client_libraries(None).and_then(|_| Version::client()) {
+                    Ok(version) => (version.to_string(), String::new()),
+                    Err(e) => (String::new(), e.to_string()),
+                };
+                let loaded = error.is_empty();
 
-                let mut sql_params: Vec<&dyn ToSql> = vec![];
-                for param in sql_params_vec.iter() {
-                    match param {
-                        SqlValue::String(s) => sql_params.push(s),
-                        SqlValue::Integer(i) => sql_params.push(i),
-                        SqlValue::Boolean(b) => sql_params.push(b),
-                    };
-                }
-                conn.query(&query, sql_params.as_slice())?;
-                evidence.push(Evidence { label: "Ran Query".to_string(), content: EvidenceContent::Textual(query.clone()) });
+                let report = if loaded {
+                    format!("Oracle Client {version} loaded")
+                } else {
+                    let lib_dir = std::env::var(CLIENT_LIB_DIR_ENV).unwrap_or_else(|_| "(not set)".to_string());
+                    format!("Oracle Client couldn't be loaded: {error}\n{CLIENT_LIB_DIR_ENV}: {lib_dir}")
+                };
+                evidence.push(Evidence { label: "Client Libraries".to_string(), content: EvidenceContent::Textual(report) });
+                output.insert("loaded".to_string(), ParameterValue::Boolean(loaded));
+                output.insert("version".to_string(), ParameterValue::String(version));
+                output.insert("error".to_string(), ParameterValue::String(error));
 
                 Ok(())
             }
         )
         .with_instruction(
-            Instruction::new("oracle-query-with-string-result", "ExecuteQueryWithStringResult", "Execute Query with String Result", "Execute a query. If the query contains dangerous words, you must allow dangerous queries.")
-                .with_parameter("query", "Query", ParameterKind::String)
-                .with_parameter("column", "Return Column", ParameterKind::String)
-                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean)
-                .with_output("result", "Result", ParameterKind::String),
-            |state: &mut Mutex<State>, params, output, evidence| {
+            Instruction::new("oracle-configure-connection", "ConfigureConnection", "Configure Connection", "Tune the current connection and any later connections: how many prepared statements are cached (keyed by their SQL text), how many rows are prefetched with each query (zero for either to use the driver's default), whether statements are committed automatically, and how many seconds a database call may take before it is cancelled (zero to never cancel).")
+                .with_parameter("stmt_cache_size", "Statement Cache Size", ParameterKind::Integer)
+                .with_parameter("prefetch_rows", "Prefetch Rows", ParameterKind::Integer)
+                .with_parameter("autocommit", "Autocommit", ParameterKind::Boolean)
+                .with_parameter("call_timeout", "Call Timeout (s)", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
                 let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
 
-                let danger_queries = ["truncate", "delete", "drop"];
-
-                let query = params["query"].value_string();
-                let column = params["column"].value_string();
-                let danger_allowed = params["dangerous"].value_bool();
-
-                if !danger_allowed {
-                    for word in query.split(' ') {
-                        let word = word.trim();
-                        if danger_queries.contains(&word.to_ascii_lowercase().as_str()) {
-                            return Err(Box::new(EngineError::DangerousQuery));
-                        }
-                    }
+                state.settings = ConnectionSettings {
+                    stmt_cache_size: match params["stmt_cache_size"].value_i32() {
+                        size if size > 0 => Some(size as u32),
+                        _ => None,
+                    },
+                    prefetch_rows: match params["prefetch_rows"].value_i32() {
+                        rows if rows > 0 => Some(rows as u32),
+                        _ => None,
+                    },
+                    autocommit: params["autocommit"].value_bool(),
+                    call_timeout: match params["call_timeout"].value_i32() {
+                        secs if secs > 0 => Some(Duration::from_secs(secs as u64)),
+                        _ => None,
+                    },
+                };
+                if let Some(conn) = &mut state.conn {
+                    state.settings.apply(conn).map_err(EngineError::from)?;
                 }
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-set-statement-cache-size", "SetStatementCacheSize", "Set Statement Cache Size", "Set how many prepared statements are cached and reused, keyed by their SQL text, leaving the rest of the connection configuration unchanged. Applies to the current connection and any later connections. Zero disables caching.")
+                .with_parameter("size", "Cache Size", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
 
-                let conn = state.conn.as_ref().ok_or(EngineError::NotYetConnected)?;
-                let sql_params_vec = state.params.clone();
-                state.params.clear();
-
-                let mut sql_params: Vec<&dyn ToSql> = vec![];
-                for param in sql_params_vec.iter() {
-                    match param {
-                        SqlValue::String(s) => sql_params.push(s),
-                        SqlValue::Integer(i) => sql_params.push(i),
-                        SqlValue::Boolean(b) => sql_params.push(b),
-                    };
+                state.settings.stmt_cache_size = Some(params["size"].value_i32().max(0) as u32);
+                if let Some(conn) = &mut state.conn {
+                    state.settings.apply(conn).map_err(EngineError::from)?;
                 }
-                let row = conn.query_row(&query, sql_params.as_slice())?;
-                evidence.push(Evidence { label: "Ran Query".to_string(), content: EvidenceContent::Textual(query.clone()) });
-                output.insert("result".to_string(), ParameterValue::String(row.get(column.as_str())?));
-
                 Ok(())
             }
         )
         .with_instruction(
-            Instruction::new("oracle-query-with-integer-result", "ExecuteQueryWithIntegerResult", "Execute Query with Integer Result", "Execute a query. If the query contains dangerous words, you must allow dangerous queries.")
-                .with_parameter("query", "Query", ParameterKind::String)
-                .with_parameter("column", "Return Column", ParameterKind::Integer)
-                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean)
-                .with_output("result", "Result", ParameterKind::String),
-            |state: &mut Mutex<State>, params, output, evidence| {
+            Instruction::new("oracle-set-max-rows", "SetMaxRows", "Set Max Rows", "Stop fetching after this many rows for queries that don't set their own limit, so an unbounded query can't exhaust memory. Zero removes the limit.")
+                .with_parameter("max_rows", "Max Rows", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
                 let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
 
-                let danger_queries = ["truncate", "delete", "drop"];
+                state.max_rows = match params["max_rows"].value_i32() {
+                    n if n > 0 => Some(n as usize),
+                    _ => None,
+                };
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-set-concurrency-limit", "SetConcurrencyLimit", "Set Concurrency Limit", "Limit how many statements may run at once on the additional connections used by background queries and load runs. Background queries wait for a free place before starting. Zero removes the limit.")
+                .with_parameter("limit", "Limit", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                state.concurrency = match params["limit"].value_i32() {
+                    n if n > 0 => Some(Arc::new(Semaphore::new(n as usize))),
+                    _ => None,
+                };
+                Ok(())
+            }
+        )
+
+        /* Named Connections */
+        .with_instruction(
+            Instruction::new("oracle-connect-named", "ConnectNamed", "Connect Named", "Store the details of an additional connection under a name, opened when it is first used. Statements keep running on the active connection until Use Connection switches to it. Values like ${env:NAME} are replaced with the environment variable NAME.")
+                .with_parameter("name", "Connection Name", ParameterKind::String)
+                .with_parameter("username", "Username", ParameterKind::String)
+                .with_parameter("password", "Password", ParameterKind::String)
+                .with_parameter("connect_string", "Connection String", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let name = params["name"].value_string().trim().to_string();
+                let username = resolve_env_refs(&params["username"].value_string())?;
+                let password = resolve_env_refs(&params["password"].value_string())?;
+                let connect_string = resolve_env_refs(&params["connect_string"].value_string())?;
+
+                if let Some(e) = &state.config_error {
+                    return Err(Box::new(EngineError::InvalidConfig(e.clone())));
+                }
+                load_client_libraries(None).map_err(EngineError::ClientLibraries)?;
+                let credentials = Credentials { username, password, connect_string };
+                state.forget_cached_results(&name);
+                if name == state.active_connection {
+                    state.conn = None;
+                    state.credentials = Some(credentials);
+                    state.uncommitted = false;
+                } else {
+                    state.other_connections.insert(name, NamedConnection { conn: None, credentials, last_used: None, uncommitted: false });
+                }
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-use-connection", "UseConnection", "Use Connection", "Run the following statements on a named connection. The connection opened by the connect instructions is named \"default\" until another is used.")
+                .with_parameter("name", "Connection Name", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                state.use_connection(params["name"].value_string().trim())?;
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-commit-all", "CommitAll", "Commit All", "Commit every open connection one at a time, the active one first and the rest by name. If a commit fails, the connections not yet committed are rolled back and the failure lists those that had already committed.")
+                .with_output("committed", "Connections Committed", ParameterKind::Integer),
+            |state: &mut Mutex<State>, _params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let names = state.open_connection_names();
+                let mut committed = vec![];
+                for (i, name) in names.iter().enumerate() {
+                    if let Err(e) = state.named_connection(name, evidence)?.commit() {
+                        for rest in &names[i..] {
+                            if let Ok(conn) = state.named_connection(rest, evidence) {
+                                if conn.rollback().is_ok() {
+                                    state.finish_transaction(rest);
+                                }
+                            }
+                        }
+                        evidence.push(Evidence {
+                            label: "Commit All".to_string(),
+                            content: EvidenceContent::Textual(format!("Committed: {}\nFailed: {name}\nRolled back: {}", committed.join(", "), names[i..].join(", "))),
+                        });
+                        return Err(Box::new(EngineError::CommitAllFailed(name.clone(), committed.join(", "), e)));
+                    }
+                    state.finish_transaction(name);
+                    committed.push(name.clone());
+                }
+
+                evidence.push(Evidence {
+                    label: "Commit All".to_string(),
+                    content: EvidenceContent::Textual(format!("Committed: {}", committed.join(", "))),
+                });
+                output.insert("committed".to_string(), ParameterValue::Integer(committed.len() as i32));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-rollback-all", "RollbackAll", "Rollback All", "Roll back every open connection, the active one first and the rest by name. Every connection is rolled back even if one fails, then the first failure is reported.")
+                .with_output("rolled_back", "Connections Rolled Back", ParameterKind::Integer),
+            |state: &mut Mutex<State>, _params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let mut rolled_back = vec![];
+                let mut first_error = None;
+                for name in state.open_connection_names() {
+                    match state.named_connection(&name, evidence).and_then(|conn| conn.rollback().map_err(EngineError::from)) {
+                        Ok(()) => {
+                            state.finish_transaction(&name);
+                            rolled_back.push(name);
+                        }
+                        Err(e) => {
+                            first_error.get_or_insert(e);
+                        }
+                    }
+                }
+
+                evidence.push(Evidence {
+                    label: "Rollback All".to_string(),
+                    content: EvidenceContent::Textual(format!("Rolled back: {}", rolled_back.join(", "))),
+                });
+                if let Some(e) = first_error {
+                    return Err(Box::new(e));
+                }
+                output.insert("rolled_back".to_string(), ParameterValue::Integer(rolled_back.len() as i32));
+
+                Ok(())
+            }
+        )
+
+        /* Add Parameters */
+        .with_instruction(
+            Instruction::new("oracle-query-add-parameter-string", "AddQueryParameterString", "Add Query Parameter: String", "Add a parameter to be used later in a query. Values like ${env:NAME} are replaced with the environment variable NAME.")
+                .with_parameter("sql_param", "Parameter Value", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let sql_param_text = params["sql_param"].value_string();
+                let sql_param = resolve_env_refs(&sql_param_text)?;
+                state.check_memory(sql_param.len())?;
+                if sql_param != sql_param_text {
+                    state.env_ref_params.insert(sql_param.clone(), sql_param_text);
+                }
+
+                state.params.push(if state.force_nchar { SqlValue::NChar(NChar(sql_param)) } else { SqlValue::String(sql_param) });
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-query-add-parameter-nstring", "AddQueryParameterNString", "Add Query Parameter: National String", "Add a parameter to be used later in a query, bound as NVARCHAR2 so that characters the database character set can't hold survive into NCHAR and NVARCHAR2 columns. Values like ${env:NAME} are replaced with the environment variable NAME.")
+                .with_parameter("sql_param", "Parameter Value", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let sql_param_text = params["sql_param"].value_string();
+                let sql_param = resolve_env_refs(&sql_param_text)?;
+                state.check_memory(sql_param.len())?;
+                if sql_param != sql_param_text {
+                    state.env_ref_params.insert(sql_param.clone(), sql_param_text);
+                }
+
+                state.params.push(SqlValue::NChar(NChar(sql_param)));
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-force-nchar-parameters", "ForceNCharParameters", "Force National String Parameters", "Turn on or off binding every string parameter added afterwards as NVARCHAR2, for flows that only write to NCHAR and NVARCHAR2 columns.")
+                .with_parameter("enabled", "Enabled", ParameterKind::Boolean),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                state.force_nchar = params["enabled"].value_bool();
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-query-add-parameter-integer", "AddQueryParameterInteger", "Add Query Parameter: Integer", "Add a parameter to be used later in a query.")
+                .with_parameter("sql_param", "Parameter Value", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let sql_param = params["sql_param"].value_i32();
+
+                state.params.push(SqlValue::Integer(sql_param as i64));
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-query-add-parameter-boolean", "AddQueryParameterBoolean", "Add Query Parameter: Boolean", "Add a parameter to be used later in a query.")
+                .with_parameter("sql_param", "Parameter Value", ParameterKind::Boolean),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let sql_param = params["sql_param"].value_bool();
+
+                state.params.push(SqlValue::Boolean(sql_param));
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-query-use-last-result", "UseLastResultAsParameter", "Use Last Result as Parameter", "Add the result of the last query with a string or integer result as a parameter to be used later in a query, keeping its type."),
+            |state: &mut Mutex<State>, _params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let value = state.last_result.clone().ok_or(EngineError::NoLastResult)?;
+                state.check_memory(value.approx_size())?;
+
+                state.params.push(value);
+                Ok(())
+            }
+        )
+
+        /* Query Hints */
+        .with_instruction(
+            Instruction::new("oracle-set-result-cache", "SetResultCache", "Set Result Cache Hint", "Add a RESULT_CACHE or NO_RESULT_CACHE hint to every following query, so that results are (or aren't) served from the server result cache. Leave it empty to stop adding a hint.")
+                .with_parameter("mode", "Mode", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let mode = params["mode"].value_string();
+
+                state.result_cache_hint = match mode.trim().to_ascii_uppercase().as_str() {
+                    "" => None,
+                    "RESULT_CACHE" => Some("RESULT_CACHE"),
+                    "NO_RESULT_CACHE" => Some("NO_RESULT_CACHE"),
+                    _ => return Err(Box::new(EngineError::InvalidResultCacheMode(mode))),
+                };
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-set-query-hints", "SetQueryHints", "Set Query Hints", "Add optimizer hints (e.g. FULL(t) PARALLEL(4)) to every following query, after its first keyword. Leave it empty to stop adding hints.")
+                .with_parameter("hints", "Hints", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let hints = params["hints"].value_string();
+
+                if hints.contains("*/") {
+                    return Err(Box::new(EngineError::InvalidHints(hints)));
+                }
+                state.query_hints = hints.trim().to_string();
+                Ok(())
+            }
+        )
+
+        /* Query Cache */
+        .with_instruction(
+            Instruction::new("oracle-set-query-cache", "SetQueryCache", "Set Query Cache", "Turn on or off reusing the results of identical queries (with identical parameters) that only read data, for lookups of static reference data. Turning it off clears the cache.")
+                .with_parameter("enabled", "Enabled", ParameterKind::Boolean),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                state.query_cache_enabled = params["enabled"].value_bool();
+                if !state.query_cache_enabled {
+                    state.query_cache.clear();
+                }
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-clear-query-cache", "ClearQueryCache", "Clear Query Cache", "Forget every cached query result, so the next queries read fresh data.")
+                .with_output("cleared", "Results Cleared", ParameterKind::Integer),
+            |state: &mut Mutex<State>, _params, output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                output.insert("cleared".to_string(), ParameterValue::Integer(state.query_cache.len() as i32));
+                state.query_cache.clear();
+                Ok(())
+            }
+        )
+
+        /* Run Query */
+        .with_instruction(
+            Instruction::new("oracle-query", "ExecuteQuery", "Execute Query", "Execute a query. If the query contains dangerous words, you must allow dangerous queries.")
+                .with_parameter("query", "Query", ParameterKind::String)
+                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean)
+                .with_output("warning", "Warning", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let query = params["query"].value_string();
+                let danger_allowed = params["dangerous"].value_bool();
+
+                check_dangerous(&query, danger_allowed || state.config.allow_dangerous_queries)?;
+                let query = state.with_hints(&query);
+
+                let sql_params_vec = std::mem::take(&mut state.params);
+                state.record(&query, &sql_params_vec)?;
+                let mut stmt = state.prepare(&query, None, false, evidence)?;
+                let sql_params: Vec<&dyn ToSql> = sql_params_vec.iter().map(SqlValue::as_to_sql).collect();
+                let started = Instant::now();
+                let result = stmt.query(sql_params.as_slice()).map(|_| ());
+                state.metrics.record(started.elapsed(), result.is_ok());
+                state.track_statement(&query);
+                result.map_err(EngineError::from)?;
+                if state.config.evidence == EvidenceVerbosity::Full {
+                    evidence.push(Evidence { label: "Ran Query".to_string(), content: EvidenceContent::Textual(query.clone()) });
+                }
+                let warning = warning_evidence(state.connection(evidence)?, evidence);
+                output.insert("warning".to_string(), ParameterValue::String(warning));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-query-with-string-result", "ExecuteQueryWithStringResult", "Execute Query with String Result", "Execute a query. Intervals are returned as ISO-8601 durations, e.g. P1DT2H3M4S. If the query contains dangerous words, you must allow dangerous queries.")
+                .with_parameter("query", "Query", ParameterKind::String)
+                .with_parameter("column", "Return Column", ParameterKind::String)
+                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean)
+                .with_output("result", "Result", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
 
                 let query = params["query"].value_string();
                 let column = params["column"].value_string();
                 let danger_allowed = params["dangerous"].value_bool();
 
-                if !danger_allowed {
-                    for word in query.split(' ') {
-                        let word = word.trim();
-                        if danger_queries.contains(&word.to_ascii_lowercase().as_str()) {
-                            return Err(Box::new(EngineError::DangerousQuery));
-                        }
+                check_dangerous(&query, danger_allowed || state.config.allow_dangerous_queries)?;
+                let query = state.with_hints(&query);
+
+                let sql_params_vec = std::mem::take(&mut state.params);
+                let cache_key = state.cache_key(&query, &column, &sql_params_vec);
+                if let Some(CachedResult::String(result)) = cache_key.as_ref().and_then(|k| state.query_cache.get(k)).cloned() {
+                    if state.config.evidence == EvidenceVerbosity::Full {
+                        evidence.push(Evidence { label: "Reused Cached Query".to_string(), content: EvidenceContent::Textual(query.clone()) });
+                    }
+                    state.last_result = Some(SqlValue::String(result.clone()));
+                    output.insert("result".to_string(), ParameterValue::String(result));
+                    return Ok(());
+                }
+
+                state.record(&query, &sql_params_vec)?;
+                let mut stmt = state.prepare(&query, None, true, evidence)?;
+                let sql_params: Vec<&dyn ToSql> = sql_params_vec.iter().map(SqlValue::as_to_sql).collect();
+                let started = Instant::now();
+                let result = stmt.query_row(sql_params.as_slice());
+                state.metrics.record(started.elapsed(), result.is_ok());
+                state.track_statement(&query);
+                let row = result.map_err(EngineError::from)?;
+                state.metrics.rows_fetched += 1;
+                if state.config.evidence == EvidenceVerbosity::Full {
+                    evidence.push(Evidence { label: "Ran Query".to_string(), content: EvidenceContent::Textual(query.clone()) });
+                }
+                warning_evidence(state.connection(evidence)?, evidence);
+                if let Some(oracle_type) = column_type(&row, &column) {
+                    state.check_memory(lob_text_size(&row, column.as_str(), oracle_type).map_err(EngineError::from)?)?;
+                }
+                let result = match column_type(&row, &column) {
+                    Some(oracle_type) => column_to_string(&row, column.as_str(), oracle_type).map_err(EngineError::from)?.ok_or(EngineError::from(oracle::Error::NullValue))?,
+                    None => row.get(column.as_str()).map_err(EngineError::from)?,
+                };
+                state.metrics.bytes_fetched += result.len() as u64;
+                state.check_memory(result.len())?;
+                if let Some(key) = cache_key {
+                    state.cache_result(key, CachedResult::String(result.clone()));
+                }
+                state.last_result = Some(SqlValue::String(result.clone()));
+                output.insert("result".to_string(), ParameterValue::String(result));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-query-with-integer-result", "ExecuteQueryWithIntegerResult", "Execute Query with Integer Result", "Execute a query. Day to second intervals are returned as a whole number of seconds. If the query contains dangerous words, you must allow dangerous queries.")
+                .with_parameter("query", "Query", ParameterKind::String)
+                .with_parameter("column", "Return Column", ParameterKind::Integer)
+                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean)
+                .with_output("result", "Result", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let query = params["query"].value_string();
+                let column = params["column"].value_string();
+                let danger_allowed = params["dangerous"].value_bool();
+
+                check_dangerous(&query, danger_allowed || state.config.allow_dangerous_queries)?;
+                let query = state.with_hints(&query);
+
+                let sql_params_vec = std::mem::take(&mut state.params);
+                let cache_key = state.cache_key(&query, &column, &sql_params_vec);
+                if let Some(CachedResult::Integer(result)) = cache_key.as_ref().and_then(|k| state.query_cache.get(k)).cloned() {
+                    if state.config.evidence == EvidenceVerbosity::Full {
+                        evidence.push(Evidence { label: "Reused Cached Query".to_string(), content: EvidenceContent::Textual(query.clone()) });
                     }
+                    state.last_result = Some(SqlValue::Integer(result as i64));
+                    output.insert("result".to_string(), ParameterValue::Integer(result));
+                    return Ok(());
                 }
 
-                let conn = state.conn.as_ref().ok_or(EngineError::NotYetConnected)?;
-                let sql_params_vec = state.params.clone();
-                state.params.clear();
+                state.record(&query, &sql_params_vec)?;
+                let mut stmt = state.prepare(&query, None, false, evidence)?;
+                let sql_params: Vec<&dyn ToSql> = sql_params_vec.iter().map(SqlValue::as_to_sql).collect();
+                let started = Instant::now();
+                let result = stmt.query_row(sql_params.as_slice());
+                state.metrics.record(started.elapsed(), result.is_ok());
+                state.track_statement(&query);
+                let row = result.map_err(EngineError::from)?;
+                state.metrics.rows_fetched += 1;
+                if state.config.evidence == EvidenceVerbosity::Full {
+                    evidence.push(Evidence { label: "Ran Query".to_string(), content: EvidenceContent::Textual(query.clone()) });
+                }
+                warning_evidence(state.connection(evidence)?, evidence);
+                state.metrics.bytes_fetched += std::mem::size_of::<i32>() as u64;
+                let result = match column_type(&row, &column) {
+                    Some(OracleType::IntervalDS(..)) => interval_ds_seconds(&row.get(column.as_str()).map_err(EngineError::from)?) as i32,
+                    _ => row.get(column.as_str()).map_err(EngineError::from)?,
+                };
+                if let Some(key) = cache_key {
+                    state.cache_result(key, CachedResult::Integer(result));
+                }
+                state.last_result = Some(SqlValue::Integer(result as i64));
+                output.insert("result".to_string(), ParameterValue::Integer(result));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-execute-batch", "ExecuteBatch", "Execute Batch", "Execute a statement once for each row of parameters in a JSON array of arrays (e.g. [[1, \"a\"], [2, \"b\"]]), sending the rows to the database in batches. If the statement contains dangerous words, you must allow dangerous queries.")
+                .with_parameter("statement", "Statement", ParameterKind::String)
+                .with_parameter("rows", "Parameter Rows (JSON)", ParameterKind::String)
+                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean)
+                .with_output("rows_affected", "Rows Affected", ParameterKind::Integer)
+                .with_output("warning", "Warning", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let statement = params["statement"].value_string();
+                let rows = params["rows"].value_string();
+                let danger_allowed = params["dangerous"].value_bool();
+
+                check_dangerous(&statement, danger_allowed || state.config.allow_dangerous_queries)?;
+                let rows = serde_json::from_str::<Vec<Vec<serde_json::Value>>>(&rows)
+                    .map_err(|_| EngineError::InvalidBatchRows)?
+                    .iter()
+                    .map(|row| row.iter().map(SqlValue::try_from).collect::<Result<Vec<_>, _>>())
+                    .collect::<Result<Vec<_>, _>>()?;
+                state.check_memory(rows.iter().flatten().map(SqlValue::approx_size).sum())?;
+                state.record_batch(&statement, &rows)?;
+
+                let conn = state.connection(evidence)?;
+                let started = Instant::now();
+                let result = execute_batch(conn, &statement, &rows);
+                state.metrics.record(started.elapsed(), result.is_ok());
+                state.track_statement(&statement);
+                let rows_affected = result.map_err(EngineError::from)?;
+
+                evidence.push(Evidence {
+                    label: "Ran Batch".to_string(),
+                    content: EvidenceContent::Textual(format!("{statement}\n\nExecuted for {} row(s) of parameters, affecting {rows_affected} row(s)", rows.len())),
+                });
+                output.insert("rows_affected".to_string(), ParameterValue::Integer(rows_affected as i32));
+                let warning = warning_evidence(state.connection(evidence)?, evidence);
+                output.insert("warning".to_string(), ParameterValue::String(warning));
+
+                Ok(())
+            }
+        )
+
+        /* Recording */
+        .with_instruction(
+            Instruction::new("oracle-start-sql-recording", "StartSqlRecording", "Start SQL Recording", "Record every statement run by the query, batch, export and background query instructions, with its parameters, to a file of one JSON object per line, so the session can be handed over or replayed. Statements are recorded before they run, so ones that fail are included. Parameters given with ${env:NAME} references are recorded as given, and resolved again when replayed.")
+                .with_parameter("path", "File Path", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let path = params["path"].value_string();
+
+                let out = BufWriter::new(File::create(&path).map_err(EngineError::RecordingFailed)?);
+                state.recording = Some(Recording { path: path.clone(), out, statements: 0 });
+
+                evidence.push(Evidence { label: "Started SQL Recording".to_string(), content: EvidenceContent::Textual(path) });
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-stop-sql-recording", "StopSqlRecording", "Stop SQL Recording", "Stop recording statements and close the recording file.")
+                .with_output("statements", "Statements Recorded", ParameterKind::Integer),
+            |state: &mut Mutex<State>, _params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let statements = match state.recording.take() {
+                    Some(recording) => {
+                        evidence.push(Evidence {
+                            label: "Stopped SQL Recording".to_string(),
+                            content: EvidenceContent::Textual(format!("{} statement(s) recorded to {}", recording.statements, recording.path)),
+                        });
+                        recording.statements
+                    }
+                    None => 0,
+                };
+                output.insert("statements".to_string(), ParameterValue::Integer(statements as i32));
+
+                Ok(())
+            }
+        )
+
+        .with_instruction(
+            Instruction::new("oracle-replay-sql-recording", "ReplaySqlRecording", "Replay SQL Recording", "Run the statements in a SQL recording, with their parameters, on the current connection, adding evidence for each. Unless stopping on errors, failed statements are counted and the replay continues. If a statement contains dangerous words, you must allow dangerous queries.")
+                .with_parameter("path", "File Path", ParameterKind::String)
+                .with_parameter("stop_on_error", "Stop on Error", ParameterKind::Boolean)
+                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean)
+                .with_output("statements", "Statements Replayed", ParameterKind::Integer)
+                .with_output("errors", "Errors", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let path = params["path"].value_string();
+                let stop_on_error = params["stop_on_error"].value_bool();
+                let danger_allowed = params["dangerous"].value_bool() || state.config.allow_dangerous_queries;
+
+                let contents = std::fs::read_to_string(&path).map_err(|e| EngineError::InvalidRecording(format!("{path}: {e}")))?;
+                let mut recorded = vec![];
+                for (i, line) in contents.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+                    let entry = serde_json::from_str::<RecordedStatement>(line)
+                        .map_err(|e| EngineError::InvalidRecording(format!("line {}: {e}", i + 1)))?;
+                    check_dangerous(&entry.statement, danger_allowed)?;
+                    let params = entry.params.iter()
+                        .map(|value| SqlValue::try_from(value)?.with_env_refs_resolved())
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let batch = entry.batch
+                        .map(|rows| rows.iter().map(|row| row.iter().map(SqlValue::try_from).collect::<Result<Vec<_>, _>>()).collect::<Result<Vec<_>, _>>())
+                        .transpose()?;
+                    recorded.push((entry.statement, params, batch));
+                }
 
-                let mut sql_params: Vec<&dyn ToSql> = vec![];
-                for param in sql_params_vec.iter() {
-                    match param {
-                        SqlValue::String(s) => sql_params.push(s),
-                        SqlValue::Integer(i) => sql_params.push(i),
-                        SqlValue::Boolean(b) => sql_params.push(b),
+                let mut errors = 0;
+                let max_rows = state.max_rows;
+                for (statement, params, batch) in &recorded {
+                    let conn = state.connection(evidence)?;
+                    let started = Instant::now();
+                    let result = replay_statement(conn, statement, params, batch.as_deref(), max_rows).map_err(EngineError::from);
+                    state.metrics.record(started.elapsed(), result.is_ok());
+                    state.track_statement(statement);
+                    let outcome = match &result {
+                        Ok(outcome) => outcome.clone(),
+                        Err(e) => {
+                            errors += 1;
+                            format!("Failed: {e}")
+                        }
                     };
+                    evidence.push(Evidence { label: "Replayed Statement".to_string(), content: EvidenceContent::Textual(format!("{statement}\n\n{outcome}")) });
+                    if stop_on_error {
+                        result.map_err(EngineError::from)?;
+                    }
                 }
-                let row = conn.query_row(&query, sql_params.as_slice())?;
-                evidence.push(Evidence { label: "Ran Query".to_string(), content: EvidenceContent::Textual(query.clone()) });
-                output.insert("result".to_string(), ParameterValue::Integer(row.get(column.as_str())?));
+
+                output.insert("statements".to_string(), ParameterValue::Integer(recorded.len() as i32));
+                output.insert("errors".to_string(), ParameterValue::Integer(errors));
 
                 Ok(())
             }
         )
-    );
-}
 
-expose_engine!(ENGINE);
+        /* Export */
+        .with_instruction(
+            Instruction::new("oracle-export-query-csv", "ExportQueryToCsv", "Export Query to CSV", "Run a query with the parameters added so far and write every row to a CSV file as it is fetched, so large results don't need to fit in memory. Progress is recorded in the evidence every so many rows (zero for none). If the query contains dangerous words, you must allow dangerous queries.")
+                .with_parameter("query", "Query", ParameterKind::String)
+                .with_parameter("path", "File Path", ParameterKind::String)
+                .with_parameter("progress_every", "Progress Every (rows)", ParameterKind::Integer)
+                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean)
+                .with_output("rows", "Rows Exported", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let query = params["query"].value_string();
+                let path = params["path"].value_string();
+                let progress_every = params["progress_every"].value_i32().max(0) as usize;
+                let danger_allowed = params["dangerous"].value_bool();
+
+                check_dangerous(&query, danger_allowed || state.config.allow_dangerous_queries)?;
+                let query = state.with_hints(&query);
+
+                let sql_params_vec = std::mem::take(&mut state.params);
+                state.record(&query, &sql_params_vec)?;
+                let mut stmt = state.prepare(&query, Some(EXPORT_FETCH_ARRAY_SIZE), false, evidence)?;
+                let sql_params: Vec<&dyn ToSql> = sql_params_vec.iter().map(SqlValue::as_to_sql).collect();
+                let started = Instant::now();
+                let result = export_rows(&mut stmt, sql_params.as_slice(), &path, ExportFormat::Csv, progress_every, evidence);
+                state.metrics.record(started.elapsed(), result.is_ok());
+                let rows = result?;
+                state.metrics.rows_fetched += rows as u64;
+                evidence.push(Evidence {
+                    label: "Exported Query to CSV".to_string(),
+                    content: EvidenceContent::Textual(format!("{query}\n\n{rows} row(s) written to {path}")),
+                });
+                output.insert("rows".to_string(), ParameterValue::Integer(rows as i32));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-export-query-json", "ExportQueryToJson", "Export Query to JSON", "Run a query with the parameters added so far and write every row to a file as a JSON array of objects as it is fetched, so large results don't need to fit in memory. Progress is recorded in the evidence every so many rows (zero for none). If the query contains dangerous words, you must allow dangerous queries.")
+                .with_parameter("query", "Query", ParameterKind::String)
+                .with_parameter("path", "File Path", ParameterKind::String)
+                .with_parameter("progress_every", "Progress Every (rows)", ParameterKind::Integer)
+                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean)
+                .with_output("rows", "Rows Exported", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let query = params["query"].value_string();
+                let path = params["path"].value_string();
+                let progress_every = params["progress_every"].value_i32().max(0) as usize;
+                let danger_allowed = params["dangerous"].value_bool();
+
+                check_dangerous(&query, danger_allowed || state.config.allow_dangerous_queries)?;
+                let query = state.with_hints(&query);
+
+                let sql_params_vec = std::mem::take(&mut state.params);
+                state.record(&query, &sql_params_vec)?;
+                let mut stmt = state.prepare(&query, Some(EXPORT_FETCH_ARRAY_SIZE), false, evidence)?;
+                let sql_params: Vec<&dyn ToSql> = sql_params_vec.iter().map(SqlValue::as_to_sql).collect();
+                let started = Instant::now();
+                let result = export_rows(&mut stmt, sql_params.as_slice(), &path, ExportFormat::Json, progress_every, evidence);
+                state.metrics.record(started.elapsed(), result.is_ok());
+                let rows = result?;
+                state.metrics.rows_fetched += rows as u64;
+                evidence.push(Evidence {
+                    label: "Exported Query to JSON".to_string(),
+                    content: EvidenceContent::Textual(format!("{query}\n\n{rows} row(s) written to {path}")),
+                });
+                output.insert("rows".to_string(), ParameterValue::Integer(rows as i32));
+
+                Ok(())
+            }
+        )
+
+        /* Background Queries */
+        .with_instruction(
+            Instruction::new("oracle-start-background-query", "StartQueryInBackground", "Start Query in Background", "Start running a query on a separate connection, using the parameters added so far, without waiting for it to finish. Use Await Background Query with the same handle to collect the result. Fetching stops after the maximum rows, or the engine's limit if zero. If the query contains dangerous words, you must allow dangerous queries.")
+                .with_parameter("handle", "Handle", ParameterKind::String)
+                .with_parameter("query", "Query", ParameterKind::String)
+                .with_parameter("max_rows", "Max Rows", ParameterKind::Integer)
+                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean),
+            |state: &mut Mutex<State>, params, _output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let handle = params["handle"].value_string();
+                let query = params["query"].value_string();
+                let max_rows = match params["max_rows"].value_i32() {
+                    n if n > 0 => Some(n as usize),
+                    _ => state.max_rows,
+                };
+                let danger_allowed = params["dangerous"].value_bool();
+
+                check_dangerous(&query, danger_allowed || state.config.allow_dangerous_queries)?;
+                if state.background_queries.contains_key(&handle) {
+                    return Err(Box::new(EngineError::BackgroundQueryExists(handle)));
+                }
+
+                let credentials = state.credentials.clone().ok_or(EngineError::NotYetConnected)?;
+                let settings = state.settings.clone();
+                let concurrency = state.concurrency.clone();
+                let sql_params_vec = std::mem::take(&mut state.params);
+                state.record(&query, &sql_params_vec)?;
+                let thread_query = query.clone();
+                let thread = std::thread::spawn(move || -> oracle::Result<BackgroundResult> {
+                    let _permit = concurrency.as_ref().map(|c| c.acquire());
+                    let conn = credentials.connect(&settings)?;
+                    let sql_params: Vec<&dyn ToSql> = sql_params_vec.iter().map(SqlValue::as_to_sql).collect();
+                    let mut result = BackgroundResult { rows: 0, first: String::new(), truncated: false };
+                    for row in conn.query(&thread_query, sql_params.as_slice())? {
+                        if max_rows.is_some_and(|max| result.rows >= max) {
+                            result.truncated = true;
+                            break;
+                        }
+                        let row = row?;
+                        if result.rows == 0 {
+                            result.first = row.get::<usize, Option<String>>(0)?.unwrap_or_default();
+                        }
+                        result.rows += 1;
+                    }
+                    Ok(result)
+                });
+
+                evidence.push(Evidence { label: format!("Started Background Query {handle}"), content: EvidenceContent::Textual(query.clone()) });
+                state.background_queries.insert(handle, BackgroundQuery { query, thread });
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-await-background-query", "AwaitBackgroundQuery", "Await Background Query", "Wait for a background query to finish, outputting the number of rows it returned, the first column of its first row and whether it stopped at the row limit.")
+                .with_parameter("handle", "Handle", ParameterKind::String)
+                .with_output("rows", "Row Count", ParameterKind::Integer)
+                .with_output("result", "Result", ParameterKind::String)
+                .with_output("truncated", "Truncated", ParameterKind::Boolean),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let handle = params["handle"].value_string();
+
+                let background = state.background_queries.remove(&handle)
+                    .ok_or_else(|| EngineError::UnknownBackgroundQuery(handle.clone()))?;
+                let started = Instant::now();
+                let result = background.thread.join()
+                    .map_err(|_| EngineError::BackgroundQueryPanicked(handle.clone()))?
+                    .map_err(EngineError::from)?;
+                state.check_memory(result.first.len())?;
+
+                let mut report = format!("{}\n\nReturned {} row(s) after waiting {}ms", background.query, result.rows, started.elapsed().as_millis());
+                if result.truncated {
+                    report.push_str("\nTruncated: the row limit was reached, so further rows weren't fetched");
+                }
+                evidence.push(Evidence { label: format!("Awaited Background Query {handle}"), content: EvidenceContent::Textual(report) });
+                output.insert("rows".to_string(), ParameterValue::Integer(result.rows as i32));
+                output.insert("result".to_string(), ParameterValue::String(result.first));
+                output.insert("truncated".to_string(), ParameterValue::Boolean(result.truncated));
+
+                Ok(())
+            }
+        )
+
+        /* Load Generation */
+        .with_instruction(
+            Instruction::new("oracle-run-load", "RunLoad", "Run Load", "Open a number of connections and execute a statement repeatedly on each of them at the same time, using the parameters added so far, until either the duration in seconds has passed or each connection has run the statement the given number of times. Zero means no limit, but at least one limit must be set. If the statement contains dangerous words, you must allow dangerous queries.")
+                .with_parameter("connections", "Connections", ParameterKind::Integer)
+                .with_parameter("statement", "Statement", ParameterKind::String)
+                .with_parameter("duration", "Duration (s)", ParameterKind::Integer)
+                .with_parameter("iterations", "Iterations per Connection", ParameterKind::Integer)
+                .with_parameter("dangerous", "Allow Dangerous Queries", ParameterKind::Boolean)
+                .with_output("executions", "Executions", ParameterKind::Integer)
+                .with_output("errors", "Errors", ParameterKind::Integer)
+                .with_output("throughput", "Throughput (per second)", ParameterKind::Decimal),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let connections = params["connections"].value_i32().max(1);
+                let statement = params["statement"].value_string();
+                let duration = params["duration"].value_i32().max(0) as u64;
+                let iterations = params["iterations"].value_i32().max(0) as u64;
+                let danger_allowed = params["dangerous"].value_bool();
+
+                check_dangerous(&statement, danger_allowed || state.config.allow_dangerous_queries)?;
+                if duration == 0 && iterations == 0 {
+                    return Err(Box::new(EngineError::UnboundedLoad));
+                }
+                if let Some(concurrency) = &state.concurrency {
+                    if connections as usize > concurrency.limit {
+                        return Err(Box::new(EngineError::ConcurrencyLimitExceeded(connections, concurrency.limit)));
+                    }
+                }
+                let deadline = (duration > 0).then(|| Instant::now() + Duration::from_secs(duration));
+
+                let credentials = state.credentials.clone().ok_or(EngineError::NotYetConnected)?;
+                let sql_params_vec = std::mem::take(&mut state.params);
+                let max_rows = state.max_rows.unwrap_or(usize::MAX);
+
+                let started = Instant::now();
+                let results = std::thread::scope(|scope| {
+                    let mut workers = vec![];
+                    for _ in 0..connections {
+                        workers.push(scope.spawn(|| {
+                            let _permit = state.concurrency.as_ref().map(|c| c.acquire());
+                            let mut executions = 0u64;
+                            let mut errors = 0u64;
+                            let mut first_error = None;
+                            let mut record_error = |e: oracle::Error| {
+                                errors += 1;
+                                first_error.get_or_insert_with(|| e.to_string());
+                            };
+
+                            match credentials.connect(&state.settings) {
+                                Ok(conn) => match conn.statement(&statement).build() {
+                                    Ok(mut stmt) => {
+                                        let sql_params: Vec<&dyn ToSql> = sql_params_vec.iter().map(SqlValue::as_to_sql).collect();
+                                        while iterations == 0 || executions < iterations {
+                                            if deadline.is_some_and(|d| Instant::now() >= d) {
+                                                break;
+                                            }
+                                            let result = if stmt.is_query() {
+                                                stmt.query(sql_params.as_slice()).and_then(|rows| rows.take(max_rows).map(|r| r.map(|_| ())).collect())
+                                            } else {
+                                                stmt.execute(sql_params.as_slice())
+                                            };
+                                            executions += 1;
+                                            if let Err(e) = result {
+                                                record_error(e);
+                                            }
+                                        }
+                                    }
+                                    Err(e) => record_error(e),
+                                },
+                                Err(e) => record_error(e),
+                            }
+                            (executions, errors, first_error)
+                        }));
+                    }
+                    workers.into_iter().map(|w| w.join()).collect::<Vec<_>>()
+                });
+                let elapsed = started.elapsed();
+
+                let mut executions = 0;
+                let mut errors = 0;
+                let mut first_error = None;
+                for result in results {
+                    let (worker_executions, worker_errors, worker_error) = result.map_err(|_| EngineError::LoadWorkerPanicked)?;
+                    executions += worker_executions;
+                    errors += worker_errors;
+                    first_error = first_error.or(worker_error);
+                }
+                let throughput = executions as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+
+                let mut report = format!(
+                    "{statement}\n\n{connections} connection(s) ran {executions} execution(s) in {:.1}s ({throughput:.1}/s) with {errors} error(s)",
+                    elapsed.as_secs_f64(),
+                );
+                if let Some(max_rows) = state.max_rows {
+                    report.push_str(&format!("\nQueries fetched at most {max_rows} row(s) each"));
+                }
+                if let Some(error) = first_error {
+                    report.push_str(&format!("\nFirst error: {error}"));
+                }
+                evidence.push(Evidence { label: "Load Run".to_string(), content: EvidenceContent::Textual(report) });
+                output.insert("executions".to_string(), ParameterValue::Integer(executions as i32));
+                output.insert("errors".to_string(), ParameterValue::Integer(errors as i32));
+                output.insert("throughput".to_string(), ParameterValue::Decimal(throughput as f32));
+
+                Ok(())
+            }
+        )
+
+        /* Advanced Queuing */
+        .with_instruction(
+            Instruction::new("oracle-enqueue-aq-message", "EnqueueAqMessage", "Enqueue AQ Message", "Enqueue a message on an Advanced Queuing queue. The payload type is RAW (the text is sent as bytes) or JSON, and must match the queue's payload type. The message is visible immediately, without a commit.")
+                .with_parameter("queue", "Queue", ParameterKind::String)
+                .with_parameter("payload_type", "Payload Type", ParameterKind::String)
+                .with_parameter("payload", "Payload", ParameterKind::String)
+                .with_output("message_id", "Message ID", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let queue = params["queue"].value_string();
+                let payload_type = params["payload_type"].value_string();
+                let payload_text = params["payload"].value_string();
+                let payload = resolve_env_refs(&payload_text)?;
+
+                // Positional binds are numbered in the order placeholders first
+                // appear in the block, so they are written in that order
+                let payload_decl = match payload_type.trim().to_ascii_uppercase().as_str() {
+                    "RAW" => "p RAW(32767) := UTL_RAW.CAST_TO_RAW(:1);",
+                    "JSON" => "p JSON := JSON(:1);",
+                    _ => return Err(Box::new(EngineError::InvalidPayloadType(payload_type))),
+                };
+                let sql = format!(
+                    "DECLARE eo DBMS_AQ.ENQUEUE_OPTIONS_T; mp DBMS_AQ.MESSAGE_PROPERTIES_T; mid RAW(16); {payload_decl} \
+                    BEGIN eo.visibility := DBMS_AQ.IMMEDIATE; \
+                        DBMS_AQ.ENQUEUE(queue_name => :2, enqueue_options => eo, message_properties => mp, payload => p, msgid => mid); \
+                        :3 := RAWTOHEX(mid); END;"
+                );
+
+                let mut stmt = state.connection(evidence)?.statement(&sql).build().map_err(EngineError::from)?;
+                stmt.bind(1, &payload).map_err(EngineError::from)?;
+                stmt.bind(2, &queue).map_err(EngineError::from)?;
+                stmt.bind(3, &OracleType::Varchar2(32)).map_err(EngineError::from)?;
+                let started = Instant::now();
+                let result = stmt.execute(&[]);
+                state.metrics.record(started.elapsed(), result.is_ok());
+                result.map_err(EngineError::from)?;
+                let message_id: String = stmt.bind_value(3).map_err(EngineError::from)?;
+
+                evidence.push(Evidence {
+                    label: "Enqueued AQ Message".to_string(),
+                    content: EvidenceContent::Textual(format!("{queue} ({message_id}): {payload_text}")),
+                });
+                output.insert("message_id".to_string(), ParameterValue::String(message_id));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-dequeue-aq-message", "DequeueAqMessage", "Dequeue AQ Message", "Dequeue the next message from an Advanced Queuing queue, waiting up to the timeout in seconds for one to arrive. The payload type is RAW (the bytes are read as text) or JSON, and must match the queue's payload type.")
+                .with_parameter("queue", "Queue", ParameterKind::String)
+                .with_parameter("payload_type", "Payload Type", ParameterKind::String)
+                .with_parameter("timeout", "Timeout (seconds)", ParameterKind::Integer)
+                .with_output("payload", "Payload", ParameterKind::String)
+                .with_output("message_id", "Message ID", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let queue = params["queue"].value_string();
+                let payload_type = params["payload_type"].value_string();
+                let timeout = params["timeout"].value_i32().max(0);
+
+                // Positional binds are numbered in the order placeholders first
+                // appear in the block, so they are written in that order
+                let (payload_decl, payload_read) = match payload_type.trim().to_ascii_uppercase().as_str() {
+                    "RAW" => ("p RAW(32767);", ":3 := UTL_RAW.CAST_TO_VARCHAR2(p);"),
+                    "JSON" => ("p JSON;", "SELECT JSON_SERIALIZE(p RETURNING VARCHAR2(32767)) INTO :3 FROM dual;"),
+                    _ => return Err(Box::new(EngineError::InvalidPayloadType(payload_type))),
+                };
+                let sql = format!(
+                    "DECLARE dopt DBMS_AQ.DEQUEUE_OPTIONS_T; mp DBMS_AQ.MESSAGE_PROPERTIES_T; mid RAW(16); {payload_decl} \
+                    BEGIN dopt.visibility := DBMS_AQ.IMMEDIATE; dopt.wait := :1; \
+                        DBMS_AQ.DEQUEUE(queue_name => :2, dequeue_options => dopt, message_properties => mp, payload => p, msgid => mid); \
+                        {payload_read} :4 := RAWTOHEX(mid); END;"
+                );
+
+                let mut stmt = state.connection(evidence)?.statement(&sql).build().map_err(EngineError::from)?;
+                stmt.bind(1, &timeout).map_err(EngineError::from)?;
+                stmt.bind(2, &queue).map_err(EngineError::from)?;
+                stmt.bind(3, &OracleType::Varchar2(32767)).map_err(EngineError::from)?;
+                stmt.bind(4, &OracleType::Varchar2(32)).map_err(EngineError::from)?;
+                let started = Instant::now();
+                let result = stmt.execute(&[]);
+                state.metrics.record(started.elapsed(), result.is_ok());
+                match result {
+                    Err(oracle::Error::OciError(err)) if err.code() == ORA_DEQUEUE_TIMEOUT => {
+                        return Err(Box::new(EngineError::DequeueTimedOut(queue)));
+                    }
+                    result => result.map_err(EngineError::from)?,
+                }
+                let payload: Option<String> = stmt.bind_value(3).map_err(EngineError::from)?;
+                let payload = payload.unwrap_or_default();
+                let message_id: String = stmt.bind_value(4).map_err(EngineError::from)?;
+                state.check_memory(payload.len())?;
+
+                evidence.push(Evidence {
+                    label: "Dequeued AQ Message".to_string(),
+                    content: EvidenceContent::Textual(format!("{queue} ({message_id}): {payload}")),
+                });
+                output.insert("payload".to_string(), ParameterValue::String(payload));
+                output.insert("message_id".to_string(), ParameterValue::String(message_id));
+
+                Ok(())
+            }
+        )
+
+        /* Schema Inspection */
+        .with_instruction(
+            Instruction::new("oracle-describe-table", "DescribeTable", "Describe Table", "Describe the columns of a table, outputting their names, data types, lengths and nullability as JSON.")
+                .with_parameter("table", "Table", ParameterKind::String)
+                .with_output("columns", "Columns (JSON)", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let (owner, table) = split_object_name(&params["table"].value_string());
+
+                let conn = state.connection(evidence)?;
+                let rows = conn.query_as::<(String, String, i64, String)>(
+                    "SELECT column_name, data_type, data_length, nullable FROM all_tab_columns \
+                        WHERE owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND table_name = :2 \
+                        ORDER BY column_id",
+                    &[&owner, &table],
+                ).map_err(EngineError::from)?;
+
+                let mut columns = vec![];
+                let mut description = format!("{:<30} {:<20} {:>6} NULLABLE\n", "COLUMN", "TYPE", "LENGTH");
+                for row in rows {
+                    let (name, data_type, length, nullable) = row.map_err(EngineError::from)?;
+                    let nullable = nullable == "Y";
+                    description.push_str(&format!("{name:<30} {data_type:<20} {length:>6} {}\n", if nullable { "Y" } else { "N" }));
+                    columns.push(json!({
+                        "name": name,
+                        "data_type": data_type,
+                        "length": length,
+                        "nullable": nullable,
+                    }));
+                }
+                if columns.is_empty() {
+                    return Err(Box::new(EngineError::TableNotFound(table)));
+                }
+
+                evidence.push(Evidence { label: format!("Description of {table}"), content: EvidenceContent::Textual(description) });
+                output.insert("columns".to_string(), ParameterValue::String(serde_json::to_string(&columns)?));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-list-tables", "ListTables", "List Tables", "List the tables in a schema whose names match a LIKE pattern. An empty schema uses the current schema and an empty pattern matches all tables.")
+                .with_parameter("schema", "Schema", ParameterKind::String)
+                .with_parameter("pattern", "Name Pattern", ParameterKind::String)
+                .with_output("tables", "Tables (JSON)", ParameterKind::String)
+                .with_output("count", "Count", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let schema = params["schema"].value_string().trim().to_ascii_uppercase();
+                let pattern = params["pattern"].value_string().trim().to_ascii_uppercase();
+
+                let conn = state.connection(evidence)?;
+                let rows = conn.query_as::<String>(
+                    "SELECT table_name FROM all_tables \
+                        WHERE owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND table_name LIKE NVL(:2, '%') \
+                        AND dropped = 'NO' ORDER BY table_name",
+                    &[&schema, &pattern],
+                ).map_err(EngineError::from)?;
+                let tables = rows.collect::<Result<Vec<_>, _>>().map_err(EngineError::from)?;
+
+                evidence.push(Evidence { label: "Tables".to_string(), content: EvidenceContent::Textual(tables.join("\n")) });
+                output.insert("count".to_string(), ParameterValue::Integer(tables.len() as i32));
+                output.insert("tables".to_string(), ParameterValue::String(serde_json::to_string(&tables)?));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-column-exists", "ColumnExists", "Column Exists", "Check whether a column exists on a table.")
+                .with_parameter("table", "Table", ParameterKind::String)
+                .with_parameter("column", "Column", ParameterKind::String)
+                .with_output("exists", "Exists", ParameterKind::Boolean),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let (owner, table) = split_object_name(&params["table"].value_string());
+                let column = params["column"].value_string().trim().to_ascii_uppercase();
+
+                let conn = state.connection(evidence)?;
+                let count = conn.query_row_as::<i64>(
+                    "SELECT COUNT(*) FROM all_tab_columns \
+                        WHERE owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND table_name = :2 AND column_name = :3",
+                    &[&owner, &table, &column],
+                ).map_err(EngineError::from)?;
+                let exists = count > 0;
+
+                evidence.push(Evidence {
+                    label: "Column Exists".to_string(),
+                    content: EvidenceContent::Textual(format!("{table}.{column} {}", if exists { "exists" } else { "does not exist" })),
+                });
+                output.insert("exists".to_string(), ParameterValue::Boolean(exists));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-check-index", "CheckIndex", "Check Index", "Check whether an index exists, either by name or, if the index name is empty, by the table and comma-separated columns (in order) it covers.")
+                .with_parameter("index", "Index Name", ParameterKind::String)
+                .with_parameter("table", "Table", ParameterKind::String)
+                .with_parameter("columns", "Columns", ParameterKind::String)
+                .with_output("exists", "Exists", ParameterKind::Boolean)
+                .with_output("index", "Index Name", ParameterKind::String)
+                .with_output("unique", "Unique", ParameterKind::Boolean)
+                .with_output("status", "Status", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let index = params["index"].value_string();
+                let conn = state.connection(evidence)?;
+
+                let (found, searched_for) = if !index.trim().is_empty() {
+                    let (owner, index) = split_object_name(&index);
+                    let found = conn.query_as::<(String, String, String)>(
+                        "SELECT index_name, uniqueness, status FROM all_indexes \
+                            WHERE owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND index_name = :2",
+                        &[&owner, &index],
+                    ).map_err(EngineError::from)?.next().transpose().map_err(EngineError::from)?;
+                    (found, index)
+                } else {
+                    let (owner, table) = split_object_name(&params["table"].value_string());
+                    let columns = params["columns"].value_string()
+                        .split(',')
+                        .map(|c| c.trim().to_ascii_uppercase())
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    let found = conn.query_as::<(String, String, String)>(
+                        "SELECT i.index_name, i.uniqueness, i.status FROM all_indexes i \
+                            WHERE i.table_owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND i.table_name = :2 \
+                            AND (SELECT LISTAGG(c.column_name, ',') WITHIN GROUP (ORDER BY c.column_position) \
+                                FROM all_ind_columns c WHERE c.index_owner = i.owner AND c.index_name = i.index_name) = :3",
+                        &[&owner, &table, &columns],
+                    ).map_err(EngineError::from)?.next().transpose().map_err(EngineError::from)?;
+                    (found, format!("{table}({columns})"))
+                };
+
+                let report = match &found {
+                    Some((name, uniqueness, status)) => format!("Index {name} found: {uniqueness}, {status}"),
+                    None => format!("No index found for {searched_for}"),
+                };
+                evidence.push(Evidence { label: "Check Index".to_string(), content: EvidenceContent::Textual(report) });
+
+                let (name, uniqueness, status) = found.clone().unwrap_or_default();
+                output.insert("exists".to_string(), ParameterValue::Boolean(found.is_some()));
+                output.insert("index".to_string(), ParameterValue::String(name));
+                output.insert("unique".to_string(), ParameterValue::Boolean(uniqueness == "UNIQUE"));
+                output.insert("status".to_string(), ParameterValue::String(status));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-check-constraint", "CheckConstraint", "Check Constraint", "Check whether a constraint exists on a table, outputting its type, status (ENABLED/DISABLED) and validation state.")
+                .with_parameter("table", "Table", ParameterKind::String)
+                .with_parameter("constraint", "Constraint Name", ParameterKind::String)
+                .with_output("exists", "Exists", ParameterKind::Boolean)
+                .with_output("type", "Type", ParameterKind::String)
+                .with_output("status", "Status", ParameterKind::String)
+                .with_output("validated", "Validated", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let table = params["table"].value_string();
+                let constraint = params["constraint"].value_string();
+
+                let conn = state.connection(evidence)?;
+                let found = find_constraint(conn, &table, &constraint).map_err(EngineError::from)?;
+
+                let report = match &found {
+                    Some((kind, status, validated)) => format!("Constraint {constraint} on {table}: {kind}, {status}, {validated}"),
+                    None => format!("Constraint {constraint} does not exist on {table}"),
+                };
+                evidence.push(Evidence { label: "Check Constraint".to_string(), content: EvidenceContent::Textual(report) });
+
+                output.insert("exists".to_string(), ParameterValue::Boolean(found.is_some()));
+                let (kind, status, validated) = found.unwrap_or_default();
+                output.insert("type".to_string(), ParameterValue::String(kind));
+                output.insert("status".to_string(), ParameterValue::String(status));
+                output.insert("validated".to_string(), ParameterValue::String(validated));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-assert-constraint", "AssertConstraint", "Assert Constraint Enabled", "Fail if a constraint doesn't exist on a table or is disabled.")
+                .with_parameter("table", "Table", ParameterKind::String)
+                .with_parameter("constraint", "Constraint Name", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let table = params["table"].value_string();
+                let constraint = params["constraint"].value_string();
+
+                let conn = state.connection(evidence)?;
+                let (kind, status, validated) = find_constraint(conn, &table, &constraint).map_err(EngineError::from)?
+                    .ok_or_else(|| EngineError::ConstraintMissing(constraint.clone()))?;
+                if status != "ENABLED" {
+                    return Err(Box::new(EngineError::ConstraintDisabled(constraint)));
+                }
+
+                evidence.push(Evidence {
+                    label: "Assert Constraint".to_string(),
+                    content: EvidenceContent::Textual(format!("Constraint {constraint} on {table}: {kind}, {status}, {validated}")),
+                });
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-check-partition", "CheckPartition", "Check Partition", "Check whether a named partition exists on a partitioned table, optionally counting the rows in it.")
+                .with_parameter("table", "Table", ParameterKind::String)
+                .with_parameter("partition", "Partition", ParameterKind::String)
+                .with_parameter("count_rows", "Count Rows", ParameterKind::Boolean)
+                .with_output("exists", "Exists", ParameterKind::Boolean)
+                .with_output("rows", "Row Count", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let table_name = params["table"].value_string();
+                let partition_name = params["partition"].value_string();
+                let count_rows = params["count_rows"].value_bool();
+
+                let (owner, table) = split_object_name(&table_name);
+                let partition = partition_name.trim().to_ascii_uppercase();
+
+                let conn = state.connection(evidence)?;
+                let exists = conn.query_row_as::<i64>(
+                    "SELECT COUNT(*) FROM all_tab_partitions \
+                        WHERE table_owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) \
+                        AND table_name = :2 AND partition_name = :3",
+                    &[&owner, &table, &partition],
+                ).map_err(EngineError::from)? > 0;
+
+                let mut report = format!("Partition {partition} {} on {table}", if exists { "exists" } else { "does not exist" });
+                let mut rows = 0;
+                if exists && count_rows {
+                    let query = format!(
+                        "SELECT COUNT(*) FROM {} PARTITION ({})",
+                        quote_object_name(&table_name)?,
+                        quote_object_name(&partition_name)?,
+                    );
+                    rows = conn.query_row_as::<i64>(&query, &[]).map_err(EngineError::from)?;
+                    report.push_str(&format!(" and contains {rows} row(s)"));
+                }
+
+                evidence.push(Evidence { label: "Check Partition".to_string(), content: EvidenceContent::Textual(report) });
+                output.insert("exists".to_string(), ParameterValue::Boolean(exists));
+                output.insert("rows".to_string(), ParameterValue::Integer(rows as i32));
+
+                Ok(())
+            }
+        )
+
+        /* Schema Comparison */
+        .with_instruction(
+            Instruction::new("oracle-compare-schemas", "CompareSchemas", "Compare Schemas", "Compare the tables, columns, indexes and constraints of a schema on two named connections, adding a report of the differences as evidence. Indexes and constraints are matched by their table and columns, as system-generated names differ between databases. An empty schema uses each connection's current schema.")
+                .with_parameter("connection_a", "Connection A", ParameterKind::String)
+                .with_parameter("connection_b", "Connection B", ParameterKind::String)
+                .with_parameter("schema", "Schema", ParameterKind::String)
+                .with_output("identical", "Identical", ParameterKind::Boolean)
+                .with_output("differences", "Differences", ParameterKind::Integer)
+                .with_output("report", "Report (JSON)", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let connection_a = params["connection_a"].value_string().trim().to_string();
+                let connection_b = params["connection_b"].value_string().trim().to_string();
+                let schema = params["schema"].value_string().trim().to_ascii_uppercase();
+
+                let metadata_a = schema_metadata(state.named_connection(&connection_a, evidence)?, &schema).map_err(EngineError::from)?;
+                let metadata_b = schema_metadata(state.named_connection(&connection_b, evidence)?, &schema).map_err(EngineError::from)?;
+                let (report, differences) = compare_schema_metadata(&metadata_a, &metadata_b);
+
+                evidence.push(Evidence {
+                    label: format!("Schema Comparison ({connection_a} vs {connection_b})"),
+                    content: EvidenceContent::Textual(serde_json::to_string_pretty(&report)?),
+                });
+                output.insert("identical".to_string(), ParameterValue::Boolean(differences == 0));
+                output.insert("differences".to_string(), ParameterValue::Integer(differences as i32));
+                output.insert("report".to_string(), ParameterValue::String(report.to_string()));
+
+                Ok(())
+            }
+        )
+
+        /* Triggers */
+        .with_instruction(
+            Instruction::new("oracle-disable-trigger", "DisableTrigger", "Disable Trigger", "Disable a trigger. If it was enabled, the engine remembers it so it can be re-enabled later with Restore Triggers.")
+                .with_parameter("trigger", "Trigger", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let name = params["trigger"].value_string();
+                let trigger = quote_object_name(&name)?;
+                let (owner, trigger_name) = split_object_name(&name);
+
+                let conn = state.connection(evidence)?;
+                let status = conn.query_row_as::<String>(
+                    "SELECT status FROM all_triggers WHERE owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND trigger_name = :2",
+                    &[&owner, &trigger_name],
+                ).map_err(EngineError::from)?;
+                conn.execute(&format!("ALTER TRIGGER {trigger} DISABLE"), &[]).map_err(EngineError::from)?;
+                // Only triggers that were enabled are restored, so the environment is left as it was found
+                let was_enabled = status == "ENABLED";
+                let disabled = (state.active_connection.clone(), trigger.clone());
+                if was_enabled && !state.disabled_triggers.contains(&disabled) {
+                    state.disabled_triggers.push(disabled);
+                }
+                let content = if was_enabled { trigger } else { format!("{trigger} (already disabled, so it won't be restored)") };
+                evidence.push(Evidence { label: "Disabled Trigger".to_string(), content: EvidenceContent::Textual(content) });
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-enable-trigger", "EnableTrigger", "Enable Trigger", "Enable a trigger.")
+                .with_parameter("trigger", "Trigger", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let trigger = quote_object_name(&params["trigger"].value_string())?;
+
+                let conn = state.connection(evidence)?;
+                conn.execute(&format!("ALTER TRIGGER {trigger} ENABLE"), &[]).map_err(EngineError::from)?;
+                let active_connection = state.active_connection.clone();
+                state.disabled_triggers.retain(|(connection, t)| *connection != active_connection || *t != trigger);
+                evidence.push(Evidence { label: "Enabled Trigger".to_string(), content: EvidenceContent::Textual(trigger) });
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-restore-triggers", "RestoreTriggers", "Restore Triggers", "Re-enable every trigger that was disabled by this engine, each on the connection it was disabled on.")
+                .with_output("count", "Triggers Restored", ParameterKind::Integer),
+            |state: &mut Mutex<State>, _params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let mut restored = vec![];
+                while let Some((connection, trigger)) = state.disabled_triggers.first().cloned() {
+                    let sql = format!("ALTER TRIGGER {trigger} ENABLE");
+                    state.named_connection(&connection, evidence)?.execute(&sql, &[]).map_err(EngineError::from)?;
+                    state.disabled_triggers.remove(0);
+                    restored.push(format!("{trigger} on {connection}"));
+                }
+
+                evidence.push(Evidence { label: "Restored Triggers".to_string(), content: EvidenceContent::Textual(restored.join("\n")) });
+                output.insert("count".to_string(), ParameterValue::Integer(restored.len() as i32));
+
+                Ok(())
+            }
+        )
+
+        /* Security */
+        .with_instruction(
+            Instruction::new("oracle-check-privilege", "CheckPrivilege", "Check Privilege", "Check whether a user or role has been granted a privilege directly. Leave the object empty to check for a system privilege (which requires access to DBA_SYS_PRIVS).")
+                .with_parameter("grantee", "Grantee", ParameterKind::String)
+                .with_parameter("privilege", "Privilege", ParameterKind::String)
+                .with_parameter("object", "Object", ParameterKind::String)
+                .with_output("granted", "Granted", ParameterKind::Boolean),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let grantee = params["grantee"].value_string().trim().to_ascii_uppercase();
+                let privilege = params["privilege"].value_string().trim().to_ascii_uppercase();
+                let object = params["object"].value_string();
+
+                let conn = state.connection(evidence)?;
+                let count = if object.trim().is_empty() {
+                    conn.query_row_as::<i64>(
+                        "SELECT COUNT(*) FROM dba_sys_privs WHERE grantee = :1 AND privilege = :2",
+                        &[&grantee, &privilege],
+                    ).map_err(EngineError::from)?
+                } else {
+                    let (owner, object) = split_object_name(&object);
+                    conn.query_row_as::<i64>(
+                        "SELECT COUNT(*) FROM all_tab_privs \
+                            WHERE grantee = :1 AND privilege = :2 \
+                            AND table_schema = NVL(:3, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND table_name = :4",
+                        &[&grantee, &privilege, &owner, &object],
+                    ).map_err(EngineError::from)?
+                };
+                let granted = count > 0;
+
+                let on_object = if object.trim().is_empty() { String::new() } else { format!(" on {}", object.trim()) };
+                evidence.push(Evidence {
+                    label: "Check Privilege".to_string(),
+                    content: EvidenceContent::Textual(format!("{privilege}{on_object} is {}granted to {grantee}", if granted { "" } else { "not " })),
+                });
+                output.insert("granted".to_string(), ParameterValue::Boolean(granted));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-encrypt-value", "EncryptValue", "Encrypt Value", "Encrypt text with DBMS_CRYPTO, returning the ciphertext as hex. The algorithm is CIPHER/CHAINING/PADDING, e.g. AES256/CBC/PKCS5. The key and initialisation vector are hex; leave the vector empty for none. Requires EXECUTE on DBMS_CRYPTO.")
+                .with_parameter("value", "Value", ParameterKind::String)
+                .with_parameter("algorithm", "Algorithm", ParameterKind::String)
+                .with_parameter("key", "Key (hex)", ParameterKind::String)
+                .with_parameter("iv", "Initialisation Vector (hex)", ParameterKind::String)
+                .with_output("ciphertext", "Ciphertext (hex)", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let value = resolve_env_refs(&params["value"].value_string())?;
+                let algorithm = params["algorithm"].value_string();
+                let key = resolve_env_refs(&params["key"].value_string())?;
+                let iv = params["iv"].value_string();
+
+                let sql = format!(
+                    "BEGIN :1 := RAWTOHEX(DBMS_CRYPTO.ENCRYPT(UTL_I18N.STRING_TO_RAW(:2, 'AL32UTF8'), {}, HEXTORAW(:3), HEXTORAW(:4))); END;",
+                    crypto_type(&algorithm)?,
+                );
+                let mut stmt = state.connection(evidence)?.statement(&sql).build().map_err(EngineError::from)?;
+                stmt.bind(1, &OracleType::Varchar2(32767)).map_err(EngineError::from)?;
+                stmt.bind(2, &value).map_err(EngineError::from)?;
+                stmt.bind(3, &key).map_err(EngineError::from)?;
+                stmt.bind(4, &iv).map_err(EngineError::from)?;
+                stmt.execute(&[]).map_err(EngineError::from)?;
+                let ciphertext: String = stmt.bind_value(1).map_err(EngineError::from)?;
+
+                evidence.push(Evidence {
+                    label: "Encrypted Value".to_string(),
+                    content: EvidenceContent::Textual(format!("{}: {ciphertext}", algorithm.trim().to_ascii_uppercase())),
+                });
+                output.insert("ciphertext".to_string(), ParameterValue::String(ciphertext));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-decrypt-value", "DecryptValue", "Decrypt Value", "Decrypt hex ciphertext with DBMS_CRYPTO, returning the text. The algorithm is CIPHER/CHAINING/PADDING, e.g. AES256/CBC/PKCS5. The key and initialisation vector are hex; leave the vector empty for none. Requires EXECUTE on DBMS_CRYPTO.")
+                .with_parameter("ciphertext", "Ciphertext (hex)", ParameterKind::String)
+                .with_parameter("algorithm", "Algorithm", ParameterKind::String)
+                .with_parameter("key", "Key (hex)", ParameterKind::String)
+                .with_parameter("iv", "Initialisation Vector (hex)", ParameterKind::String)
+                .with_output("value", "Value", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let ciphertext = params["ciphertext"].value_string();
+                let algorithm = params["algorithm"].value_string();
+                let key = resolve_env_refs(&params["key"].value_string())?;
+                let iv = params["iv"].value_string();
+
+                let sql = format!(
+                    "BEGIN :1 := UTL_I18N.RAW_TO_CHAR(DBMS_CRYPTO.DECRYPT(HEXTORAW(:2), {}, HEXTORAW(:3), HEXTORAW(:4)), 'AL32UTF8'); END;",
+                    crypto_type(&algorithm)?,
+                );
+                let mut stmt = state.connection(evidence)?.statement(&sql).build().map_err(EngineError::from)?;
+                stmt.bind(1, &OracleType::Varchar2(32767)).map_err(EngineError::from)?;
+                stmt.bind(2, &ciphertext).map_err(EngineError::from)?;
+                stmt.bind(3, &key).map_err(EngineError::from)?;
+                stmt.bind(4, &iv).map_err(EngineError::from)?;
+                stmt.execute(&[]).map_err(EngineError::from)?;
+                let value: Option<String> = stmt.bind_value(1).map_err(EngineError::from)?;
+                let value = value.unwrap_or_default();
+
+                evidence.push(Evidence {
+                    label: "Decrypted Value".to_string(),
+                    content: EvidenceContent::Textual(format!("{}: {value}", algorithm.trim().to_ascii_uppercase())),
+                });
+                output.insert("value".to_string(), ParameterValue::String(value));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-hash-value", "HashValue", "Hash Value", "Hash text on the server with STANDARD_HASH, returning the hex digest. The algorithm is MD5, SHA1, SHA256, SHA384 or SHA512.")
+                .with_parameter("value", "Value", ParameterKind::String)
+                .with_parameter("algorithm", "Algorithm", ParameterKind::String)
+                .with_output("digest", "Digest (hex)", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let value = resolve_env_refs(&params["value"].value_string())?;
+                let algorithm = params["algorithm"].value_string().trim().to_ascii_uppercase();
+                if !HASH_ALGORITHMS.contains(&algorithm.as_str()) {
+                    return Err(Box::new(EngineError::InvalidHashAlgorithm(algorithm)));
+                }
+
+                let conn = state.connection(evidence)?;
+                let digest = conn.query_row_as::<String>(
+                    &format!("SELECT RAWTOHEX(STANDARD_HASH(:1, '{algorithm}')) FROM dual"),
+                    &[&value],
+                ).map_err(EngineError::from)?;
+
+                evidence.push(Evidence {
+                    label: "Hashed Value".to_string(),
+                    content: EvidenceContent::Textual(format!("{algorithm}: {digest}")),
+                });
+                output.insert("digest".to_string(), ParameterValue::String(digest));
+
+                Ok(())
+            }
+        )
+
+        /* Environment Checks */
+        .with_instruction(
+            Instruction::new("oracle-test-db-link", "TestDbLink", "Test Database Link", "Check that a database link is working by selecting from DUAL over it, within a timeout in seconds.")
+                .with_parameter("link", "Database Link", ParameterKind::String)
+                .with_parameter("timeout", "Timeout (s)", ParameterKind::Integer)
+                .with_output("success", "Success", ParameterKind::Boolean)
+                .with_output("latency", "Latency (ms)", ParameterKind::Integer)
+                .with_output("error", "Error", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let link = params["link"].value_string().trim().to_string();
+                let timeout = params["timeout"].value_i32().max(1) as u64;
+
+                if link.is_empty() || !link.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '$' | '#' | '.' | '@')) {
+                    return Err(Box::new(EngineError::InvalidIdentifier(link)));
+                }
+
+                let conn = state.connection(evidence)?;
+                let previous_timeout = conn.call_timeout().map_err(EngineError::from)?;
+                conn.set_call_timeout(Some(Duration::from_secs(timeout))).map_err(EngineError::from)?;
+                let start = Instant::now();
+                let result = conn.query_row_as::<i64>(&format!("SELECT 1 FROM dual@{link}"), &[]);
+                let latency = start.elapsed();
+                conn.set_call_timeout(previous_timeout).map_err(EngineError::from)?;
+
+                let error = result.err().map(|e| e.to_string()).unwrap_or_default();
+                let success = error.is_empty();
+                let report = if success {
+                    format!("Database link {link} responded in {}ms", latency.as_millis())
+                } else {
+                    format!("Database link {link} failed after {}ms: {error}", latency.as_millis())
+                };
+
+                evidence.push(Evidence { label: "Test Database Link".to_string(), content: EvidenceContent::Textual(report) });
+                output.insert("success".to_string(), ParameterValue::Boolean(success));
+                output.insert("latency".to_string(), ParameterValue::Integer(latency.as_millis() as i32));
+                output.insert("error".to_string(), ParameterValue::String(error));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-resolve-synonym", "ResolveSynonym", "Resolve Synonym", "Follow a chain of synonyms to the object it finally refers to, and check that the object exists and is valid.")
+                .with_parameter("synonym", "Synonym", ParameterKind::String)
+                .with_output("target", "Target", ParameterKind::String)
+                .with_output("exists", "Target Exists", ParameterKind::Boolean)
+                .with_output("valid", "Target Valid", ParameterKind::Boolean),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let synonym = params["synonym"].value_string();
+
+                let conn = state.connection(evidence)?;
+                let resolved = resolve_synonym(conn, &synonym)?;
+
+                let all_objects = match &resolved.db_link {
+                    Some(link) => format!("all_objects@{link}"),
+                    None => "all_objects".to_string(),
+                };
+                let (count, invalid) = conn.query_row_as::<(i64, i64)>(
+                    &format!("SELECT COUNT(*), COUNT(CASE WHEN status <> 'VALID' THEN 1 END) FROM {all_objects} \
+                        WHERE owner = :1 AND object_name = :2 AND object_type <> 'SYNONYM'"),
+                    &[&resolved.owner, &resolved.name],
+                ).map_err(EngineError::from)?;
+                let exists = count > 0;
+                let valid = exists && invalid == 0;
+
+                let mut target = format!("{}.{}", resolved.owner, resolved.name);
+                if let Some(link) = &resolved.db_link {
+                    target.push_str(&format!("@{link}"));
+                }
+                let mut report = resolved.chain.iter().map(|s| format!("{s} ->\n")).collect::<String>();
+                report.push_str(&format!(
+                    "{target} ({})",
+                    if valid { "valid" } else if exists { "invalid" } else { "does not exist" },
+                ));
+
+                evidence.push(Evidence { label: "Resolve Synonym".to_string(), content: EvidenceContent::Textual(report) });
+                output.insert("target".to_string(), ParameterValue::String(target));
+                output.insert("exists".to_string(), ParameterValue::Boolean(exists));
+                output.insert("valid".to_string(), ParameterValue::Boolean(valid));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-check-tablespace-usage", "CheckTablespaceUsage", "Check Tablespace Usage", "Check the used and free space in a tablespace. If the maximum percentage is above zero, fail when the tablespace is fuller than it. Requires access to DBA_TABLESPACE_USAGE_METRICS.")
+                .with_parameter("tablespace", "Tablespace", ParameterKind::String)
+                .with_parameter("max_percent", "Maximum Used Percentage", ParameterKind::Decimal)
+                .with_output("used_mb", "Used (MB)", ParameterKind::Decimal)
+                .with_output("free_mb", "Free (MB)", ParameterKind::Decimal)
+                .with_output("used_percent", "Used Percentage", ParameterKind::Decimal),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let tablespace = params["tablespace"].value_string().trim().to_ascii_uppercase();
+                let max_percent = params["max_percent"].value_f32() as f64;
+
+                let conn = state.connection(evidence)?;
+                let (used, free, used_percent) = conn.query_as::<(f64, f64, f64)>(
+                    "SELECT m.used_space * t.block_size, (m.tablespace_size - m.used_space) * t.block_size, m.used_percent \
+                        FROM dba_tablespace_usage_metrics m JOIN dba_tablespaces t ON t.tablespace_name = m.tablespace_name \
+                        WHERE m.tablespace_name = :1",
+                    &[&tablespace],
+                ).map_err(EngineError::from)?
+                .next()
+                .transpose().map_err(EngineError::from)?
+                .ok_or_else(|| EngineError::TablespaceNotFound(tablespace.clone()))?;
+                let used_mb = used / 1024.0 / 1024.0;
+                let free_mb = free / 1024.0 / 1024.0;
+
+                evidence.push(Evidence {
+                    label: "Tablespace Usage".to_string(),
+                    content: EvidenceContent::Textual(format!("{tablespace}: {used_mb:.1}MB used, {free_mb:.1}MB free ({used_percent:.1}% used)")),
+                });
+                if max_percent > 0.0 && used_percent > max_percent {
+                    return Err(Box::new(EngineError::TablespaceThresholdExceeded(tablespace, used_percent, max_percent)));
+                }
+
+                output.insert("used_mb".to_string(), ParameterValue::Decimal(used_mb as f32));
+                output.insert("free_mb".to_string(), ParameterValue::Decimal(free_mb as f32));
+                output.insert("used_percent".to_string(), ParameterValue::Decimal(used_percent as f32));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-instance-status", "GetInstanceStatus", "Get Instance Status", "Get the status, open mode and startup time of the connected instance. Requires access to V$INSTANCE and V$DATABASE.")
+                .with_output("status", "Instance Status", ParameterKind::String)
+                .with_output("open_mode", "Open Mode", ParameterKind::String)
+                .with_output("startup_time", "Startup Time", ParameterKind::String)
+                .with_output("uptime", "Uptime (s)", ParameterKind::Integer),
+            |state: &mut Mutex<State>, _params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let conn = state.connection(evidence)?;
+                let (status, open_mode, startup_time, uptime) = conn.query_row_as::<(String, String, String, i64)>(
+                    "SELECT i.status, d.open_mode, TO_CHAR(i.startup_time, 'YYYY-MM-DD\"T\"HH24:MI:SS'), \
+                        ROUND((SYSDATE - i.startup_time) * 86400) FROM v$instance i CROSS JOIN v$database d",
+                    &[],
+                ).map_err(EngineError::from)?;
+
+                evidence.push(Evidence {
+                    label: "Instance Status".to_string(),
+                    content: EvidenceContent::Textual(format!("Status: {status}\nOpen mode: {open_mode}\nStarted: {startup_time} ({uptime}s ago)")),
+                });
+                output.insert("status".to_string(), ParameterValue::String(status));
+                output.insert("open_mode".to_string(), ParameterValue::String(open_mode));
+                output.insert("startup_time".to_string(), ParameterValue::String(startup_time));
+                output.insert("uptime".to_string(), ParameterValue::Integer(uptime as i32));
+
+                Ok(())
+            }
+        )
+
+        /* Session Information */
+        .with_instruction(
+            Instruction::new("oracle-session-context", "GetSessionContext", "Get Session Context", "Get the session user, current schema, client host and instance name of the current connection.")
+                .with_output("session_user", "Session User", ParameterKind::String)
+                .with_output("current_schema", "Current Schema", ParameterKind::String)
+                .with_output("host", "Host", ParameterKind::String)
+                .with_output("instance", "Instance", ParameterKind::String),
+            |state: &mut Mutex<State>, _params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let conn = state.connection(evidence)?;
+                let (session_user, current_schema, host, instance) = conn.query_row_as::<(String, String, Option<String>, Option<String>)>(
+                    "SELECT SYS_CONTEXT('USERENV', 'SESSION_USER'), SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA'), \
+                        SYS_CONTEXT('USERENV', 'HOST'), SYS_CONTEXT('USERENV', 'INSTANCE_NAME') FROM dual",
+                    &[],
+                ).map_err(EngineError::from)?;
+                let host = host.unwrap_or_default();
+                let instance = instance.unwrap_or_default();
+
+                evidence.push(Evidence {
+                    label: "Session Context".to_string(),
+                    content: EvidenceContent::Textual(format!("Session user: {session_user}\nCurrent schema: {current_schema}\nHost: {host}\nInstance: {instance}")),
+                });
+                output.insert("session_user".to_string(), ParameterValue::String(session_user));
+                output.insert("current_schema".to_string(), ParameterValue::String(current_schema));
+                output.insert("host".to_string(), ParameterValue::String(host));
+                output.insert("instance".to_string(), ParameterValue::String(instance));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-get-character-sets", "GetCharacterSets", "Get Character Sets", "Get the database and national character sets, the client encoding and NLS_LANG, to diagnose text that doesn't round-trip. The client encoding is always UTF-8 for both character sets.")
+                .with_output("database", "Database Character Set", ParameterKind::String)
+                .with_output("national", "National Character Set", ParameterKind::String)
+                .with_output("client", "Client Encoding", ParameterKind::String)
+                .with_output("nls_lang", "NLS_LANG", ParameterKind::String),
+            |state: &mut Mutex<State>, _params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let conn = state.connection(evidence)?;
+                let (database, national) = conn.query_row_as::<(String, String)>(
+                    "SELECT MAX(DECODE(parameter, 'NLS_CHARACTERSET', value)), MAX(DECODE(parameter, 'NLS_NCHAR_CHARACTERSET', value)) \
+                        FROM nls_database_parameters WHERE parameter IN ('NLS_CHARACTERSET', 'NLS_NCHAR_CHARACTERSET')",
+                    &[],
+                ).map_err(EngineError::from)?;
+                let nls_lang = std::env::var("NLS_LANG").unwrap_or_default();
+
+                evidence.push(Evidence {
+                    label: "Character Sets".to_string(),
+                    content: EvidenceContent::Textual(format!(
+                        "Database: {database}\nNational: {national}\nClient: {CLIENT_ENCODING}\nNLS_LANG: {}",
+                        if nls_lang.is_empty() { "(not set)" } else { &nls_lang },
+                    )),
+                });
+                output.insert("database".to_string(), ParameterValue::String(database));
+                output.insert("national".to_string(), ParameterValue::String(national));
+                output.insert("client".to_string(), ParameterValue::String(CLIENT_ENCODING.to_string()));
+                output.insert("nls_lang".to_string(), ParameterValue::String(nls_lang));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-session-id", "GetSessionId", "Get Session ID", "Get the SID, serial number and audit session ID of the engine's session, to correlate with server-side traces. The audit session ID is output as text, since it can be too large for an integer (such as 4294967295 for SYS). Requires access to V$SESSION.")
+                .with_output("sid", "SID", ParameterKind::Integer)
+                .with_output("serial", "Serial#", ParameterKind::Integer)
+                .with_output("audsid", "Audit Session ID", ParameterKind::String),
+            |state: &mut Mutex<State>, _params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let conn = state.connection(evidence)?;
+                let (sid, serial, audsid) = conn.query_row_as::<(i64, i64, i64)>(
+                    "SELECT sid, serial#, audsid FROM v$session WHERE sid = SYS_CONTEXT('USERENV', 'SID')",
+                    &[],
+                ).map_err(EngineError::from)?;
+
+                evidence.push(Evidence {
+                    label: "Session ID".to_string(),
+                    content: EvidenceContent::Textual(format!("SID: {sid}\nSerial#: {serial}\nAUDSID: {audsid}")),
+                });
+                output.insert("sid".to_string(), ParameterValue::Integer(sid as i32));
+                output.insert("serial".to_string(), ParameterValue::Integer(serial as i32));
+                output.insert("audsid".to_string(), ParameterValue::String(audsid.to_string()));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-set-application-context", "SetApplicationContext", "Set Application Context", "Set an attribute of an application context for this session with DBMS_SESSION.SET_CONTEXT, so row-level security (VPD) policies can be exercised under different values. Oracle only allows this if the context trusts the engine's session to set it.")
+                .with_parameter("namespace", "Namespace", ParameterKind::String)
+                .with_parameter("attribute", "Attribute", ParameterKind::String)
+                .with_parameter("value", "Value", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let namespace = params["namespace"].value_string();
+                let attribute = params["attribute"].value_string();
+                let value_text = params["value"].value_string();
+                let value = resolve_env_refs(&value_text)?;
+
+                let conn = state.connection(evidence)?;
+                conn.execute("BEGIN DBMS_SESSION.SET_CONTEXT(:1, :2, :3); END;", &[&namespace, &attribute, &value]).map_err(EngineError::from)?;
+
+                evidence.push(Evidence {
+                    label: "Set Application Context".to_string(),
+                    content: EvidenceContent::Textual(format!("{namespace}.{attribute} = {value_text}")),
+                });
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-switch-consumer-group", "SwitchConsumerGroup", "Switch Consumer Group", "Switch this session to a Resource Manager consumer group, to check how work in that group is throttled. Requires the switch privilege for the group.")
+                .with_parameter("group", "Consumer Group", ParameterKind::String)
+                .with_output("previous", "Previous Consumer Group", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let group = params["group"].value_string().trim().to_ascii_uppercase();
+
+                let mut stmt = state.connection(evidence)?.statement(
+                    "BEGIN DBMS_SESSION.SWITCH_CURRENT_CONSUMER_GROUP(:1, :2, FALSE); END;",
+                ).build().map_err(EngineError::from)?;
+                stmt.bind(1, &group).map_err(EngineError::from)?;
+                stmt.bind(2, &OracleType::Varchar2(128)).map_err(EngineError::from)?;
+                stmt.execute(&[]).map_err(EngineError::from)?;
+                let previous: Option<String> = stmt.bind_value(2).map_err(EngineError::from)?;
+                let previous = previous.unwrap_or_default();
+
+                evidence.push(Evidence {
+                    label: "Switched Consumer Group".to_string(),
+                    content: EvidenceContent::Textual(format!("{previous} -> {group}")),
+                });
+                output.insert("previous".to_string(), ParameterValue::String(previous));
+
+                Ok(())
+            }
+        )
+
+        /* Locks and Sessions */
+        .with_instruction(
+            Instruction::new("oracle-get-blocking-locks", "GetBlockingLocks", "Get Blocking Locks", "Get the sessions that are blocking other sessions, optionally only those holding locks on a table. Leave the table empty to check globally. Requires access to V$SESSION and V$LOCKED_OBJECT.")
+                .with_parameter("table", "Table", ParameterKind::String)
+                .with_output("blockers", "Blocking Sessions (JSON)", ParameterKind::String)
+                .with_output("count", "Count", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let table = params["table"].value_string();
+
+                let conn = state.connection(evidence)?;
+                let mut query = "SELECT s.sid, s.serial#, s.username, s.program, s.sql_id, \
+                    (SELECT COUNT(*) FROM v$session w WHERE w.blocking_session = s.sid) \
+                    FROM v$session s WHERE s.sid IN (SELECT blocking_session FROM v$session WHERE blocking_session IS NOT NULL)".to_string();
+                type Blocker = (i64, i64, Option<String>, Option<String>, Option<String>, i64);
+                let rows = if table.trim().is_empty() {
+                    conn.query_as::<Blocker>(&query, &[]).map_err(EngineError::from)?
+                } else {
+                    let (owner, table) = split_object_name(&table);
+                    query.push_str(" AND s.sid IN (SELECT lo.session_id FROM v$locked_object lo \
+                        JOIN all_objects o ON o.object_id = lo.object_id \
+                        WHERE o.owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND o.object_name = :2)");
+                    conn.query_as::<Blocker>(&query, &[&owner, &table]).map_err(EngineError::from)?
+                };
+
+                let mut blockers = vec![];
+                let mut report = String::new();
+                for row in rows {
+                    let (sid, serial, username, program, sql_id, waiting) = row.map_err(EngineError::from)?;
+                    report.push_str(&format!(
+                        "SID {sid}, serial# {serial}: user {}, program {}, SQL ID {}, blocking {waiting} session(s)\n",
+                        username.as_deref().unwrap_or("-"),
+                        program.as_deref().unwrap_or("-"),
+                        sql_id.as_deref().unwrap_or("-"),
+                    ));
+                    blockers.push(json!({
+                        "sid": sid,
+                        "serial": serial,
+                        "username": username,
+                        "program": program,
+                        "sql_id": sql_id,
+                        "waiting_sessions": waiting,
+                    }));
+                }
+                if blockers.is_empty() {
+                    report.push_str("No blocking sessions");
+                }
+
+                evidence.push(Evidence { label: "Blocking Locks".to_string(), content: EvidenceContent::Textual(report) });
+                output.insert("count".to_string(), ParameterValue::Integer(blockers.len() as i32));
+                output.insert("blockers".to_string(), ParameterValue::String(serde_json::to_string(&blockers)?));
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-kill-session", "KillSession", "Kill Session", "Kill another database session, rolling back its work. You must confirm that you understand this before the session will be killed.")
+                .with_parameter("sid", "SID", ParameterKind::Integer)
+                .with_parameter("serial", "Serial#", ParameterKind::Integer)
+                .with_parameter("i_understand", "I understand this will kill the session", ParameterKind::Boolean),
+            |state: &mut Mutex<State>, params, _output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let sid = params["sid"].value_i32();
+                let serial = params["serial"].value_i32();
+                if !params["i_understand"].value_bool() {
+                    return Err(Box::new(EngineError::KillNotConfirmed));
+                }
+
+                let conn = state.connection(evidence)?;
+                conn.execute(&format!("ALTER SYSTEM KILL SESSION '{sid},{serial}' IMMEDIATE"), &[]).map_err(EngineError::from)?;
+                evidence.push(Evidence { label: "Killed Session".to_string(), content: EvidenceContent::Textual(format!("SID {sid}, serial# {serial}")) });
+
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-wait-for-lock-release", "WaitForLockRelease", "Wait for Lock Release", "Wait until no other session holds a lock on a table, failing if the timeout in seconds expires first. Requires access to V$LOCKED_OBJECT.")
+                .with_parameter("table", "Table", ParameterKind::String)
+                .with_parameter("timeout", "Timeout (s)", ParameterKind::Integer)
+                .with_output("waited", "Time Waited (ms)", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let (owner, table) = split_object_name(&params["table"].value_string());
+                let timeout = Duration::from_secs(params["timeout"].value_i32().max(0) as u64);
+
+                let conn = state.connection(evidence)?;
+                let start = Instant::now();
+                loop {
+                    let locks = conn.query_row_as::<i64>(
+                        "SELECT COUNT(*) FROM v$locked_object lo JOIN all_objects o ON o.object_id = lo.object_id \
+                            WHERE o.owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND o.object_name = :2 \
+                            AND lo.session_id <> SYS_CONTEXT('USERENV', 'SID')",
+                        &[&owner, &table],
+                    ).map_err(EngineError::from)?;
+                    if locks == 0 {
+                        break;
+                    }
+                    if start.elapsed() >= timeout {
+                        return Err(Box::new(EngineError::LockWaitTimedOut(table)));
+                    }
+                    std::thread::sleep(LOCK_POLL_INTERVAL);
+                }
+                let waited = start.elapsed();
+
+                evidence.push(Evidence {
+                    label: "Lock Released".to_string(),
+                    content: EvidenceContent::Textual(format!("No other session holds a lock on {table} (waited {}ms)", waited.as_millis())),
+                });
+                output.insert("waited".to_string(), ParameterValue::Integer(waited.as_millis() as i32));
+
+                Ok(())
+            }
+        )
+
+        /* Change Notification */
+        .with_instruction(
+            Instruction::new("oracle-wait-for-table-change", "WaitForTableChange", "Wait for Table Change", "Wait until another session commits a change to a table, failing if the timeout in seconds expires first. The driver doesn't expose continuous query notification, so the table's row count and latest change SCN are checked frequently instead.")
+                .with_parameter("table", "Table", ParameterKind::String)
+                .with_parameter("timeout", "Timeout (s)", ParameterKind::Integer)
+                .with_output("waited", "Time Waited (ms)", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let table = params["table"].value_string();
+                let timeout = Duration::from_secs(params["timeout"].value_i32().max(0) as u64);
+                let sql = format!("SELECT COUNT(*), TO_CHAR(MAX(ORA_ROWSCN)) FROM {}", quote_object_name(&table)?);
+
+                let conn = state.connection(evidence)?;
+                let mut stmt = conn.statement(&sql).build().map_err(EngineError::from)?;
+                let baseline = stmt.query_row_as::<(i64, Option<String>)>(&[]).map_err(EngineError::from)?;
+                let start = Instant::now();
+                loop {
+                    std::thread::sleep(CHANGE_POLL_INTERVAL);
+                    if stmt.query_row_as::<(i64, Option<String>)>(&[]).map_err(EngineError::from)? != baseline {
+                        break;
+                    }
+                    if start.elapsed() >= timeout {
+                        return Err(Box::new(EngineError::TableChangeTimedOut(table)));
+                    }
+                }
+                let waited = start.elapsed();
+
+                evidence.push(Evidence {
+                    label: "Table Changed".to_string(),
+                    content: EvidenceContent::Textual(format!("{table} changed after {}ms", waited.as_millis())),
+                });
+                output.insert("waited".to_string(), ParameterValue::Integer(waited.as_millis() as i32));
+
+                Ok(())
+            }
+        )
+
+        /* Statistics */
+        .with_instruction(
+            Instruction::new("oracle-gather-table-stats", "GatherTableStats", "Gather Table Statistics", "Gather optimizer statistics for a table and its indexes, invalidating dependent cursors immediately. An empty schema uses the current schema.")
+                .with_parameter("schema", "Schema", ParameterKind::String)
+                .with_parameter("table", "Table", ParameterKind::String)
+                .with_output("rows", "Row Count", ParameterKind::Integer),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let schema = params["schema"].value_string().trim().to_ascii_uppercase();
+                let table = params["table"].value_string().trim().to_ascii_uppercase();
+
+                let conn = state.connection(evidence)?;
+                conn.execute(
+                    "BEGIN DBMS_STATS.GATHER_TABLE_STATS(ownname => NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')), \
+                        tabname => :2, cascade => TRUE, no_invalidate => FALSE); END;",
+                    &[&schema, &table],
+                ).map_err(EngineError::from)?;
+                let rows = conn.query_row_as::<Option<i64>>(
+                    "SELECT num_rows FROM all_tables WHERE owner = NVL(:1, SYS_CONTEXT('USERENV', 'CURRENT_SCHEMA')) AND table_name = :2",
+                    &[&schema, &table],
+                ).map_err(EngineError::from)?.unwrap_or_default();
+
+                evidence.push(Evidence {
+                    label: "Gathered Table Statistics".to_string(),
+                    content: EvidenceContent::Textual(format!("{table}: {rows} row(s)")),
+                });
+                output.insert("rows".to_string(), ParameterValue::Integer(rows as i32));
+
+                Ok(())
+            }
+        )
+
+        /* Variables */
+        .with_instruction(
+            Instruction::new("oracle-set-variable", "SetVariable", "Set Variable", "Stash a value under a name in the engine, to get later in the flow without passing it through every step.")
+                .with_parameter("name", "Name", ParameterKind::String)
+                .with_parameter("value", "Value", ParameterKind::String),
+            |state: &mut Mutex<State>, params, _output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let name = params["name"].value_string();
+                let value = params["value"].value_string();
+
+                state.variables.insert(name, value);
+                Ok(())
+            }
+        )
+        .with_instruction(
+            Instruction::new("oracle-get-variable", "GetVariable", "Get Variable", "Get a value stashed under a name with Set Variable.")
+                .with_parameter("name", "Name", ParameterKind::String)
+                .with_output("value", "Value", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, _evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let name = params["name"].value_string();
+
+                let value = state.variables.get(&name).cloned().ok_or(EngineError::UnknownVariable(name))?;
+                output.insert("value".to_string(), ParameterValue::String(value));
+                Ok(())
+            }
+        )
+
+        /* Metrics */
+        .with_instruction(
+            Instruction::new("oracle-get-engine-metrics", "GetEngineMetrics", "Get Engine Metrics", "Get counters of the statements run by the query, batch, export, replay and Advanced Queuing instructions, their errors, timings and data fetched, and of reconnections, in PROMETHEUS text format or as JSON. Background queries, load runs and the inspection and security instructions aren't counted.")
+                .with_parameter("format", "Format", ParameterKind::String)
+                .with_output("metrics", "Metrics", ParameterKind::String),
+            |state: &mut Mutex<State>, params, output, evidence| {
+                let state = state.get_mut().map_err(|_| EngineError::PoisonedState)?;
+
+                let format = params["format"].value_string();
+
+                let metrics = match format.trim().to_ascii_uppercase().as_str() {
+                    "PROMETHEUS" => state.metrics.to_prometheus(),
+                    "JSON" => state.metrics.to_json().to_string(),
+                    _ => return Err(Box::new(EngineError::InvalidMetricsFormat(format))),
+                };
+
+                evidence.push(Evidence { label: "Engine Metrics".to_string(), content: EvidenceContent::Textual(metrics.clone()) });
+                output.insert("metrics".to_string(), ParameterValue::String(metrics));
+
+                Ok(())
+            }
+        )
+    );
+}
+
+expose_engine!(ENGINE);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db_error(code: i32, message: &str) -> oracle::Error {
+        oracle::Error::OciError(oracle::DbError::new(
+            code,
+            0,
+            message.to_string(),
+            String::new(),
+            String::new(),
+        ))
+    }
+
+    #[test]
+    fn interval_ds_formats_as_iso8601() {
+        let interval = IntervalDS::new(1, 2, 3, 4, 500_000_000).unwrap();
+        assert_eq!(interval_ds_to_iso8601(&interval), "P1DT2H3M4.5S");
+        assert_eq!(interval_ds_seconds(&interval), 93_784);
+    }
+
+    #[test]
+    fn interval_ds_keeps_the_sign_of_negative_intervals() {
+        let interval = IntervalDS::new(-1, -2, -3, -4, -500_000_000).unwrap();
+        assert_eq!(interval_ds_to_iso8601(&interval), "-P1DT2H3M4.5S");
+        assert_eq!(interval_ds_seconds(&interval), -93_784);
+
+        let interval = IntervalDS::new(0, 0, 0, 0, -250_000_000).unwrap();
+        assert_eq!(interval_ds_to_iso8601(&interval), "-P0DT0H0M0.25S");
+    }
+
+    #[test]
+    fn interval_ds_keeps_leading_zeros_of_fractional_seconds() {
+        let interval = IntervalDS::new(0, 0, 0, 7, 123_000).unwrap();
+        assert_eq!(interval_ds_to_iso8601(&interval), "P0DT0H0M7.000123S");
+        assert_eq!(interval_ds_seconds(&interval), 7);
+    }
+
+    #[test]
+    fn interval_ym_formats_as_iso8601() {
+        assert_eq!(
+            interval_ym_to_iso8601(&IntervalYM::new(1, 2).unwrap()),
+            "P1Y2M"
+        );
+        assert_eq!(
+            interval_ym_to_iso8601(&IntervalYM::new(-1, -2).unwrap()),
+            "-P1Y2M"
+        );
+        assert_eq!(
+            interval_ym_to_iso8601(&IntervalYM::new(0, -3).unwrap()),
+            "-P0Y3M"
+        );
+    }
+
+    #[test]
+    fn hints_follow_the_first_keyword() {
+        assert_eq!(
+            inject_hint("  \n SELECT * FROM dual", "RESULT_CACHE"),
+            "SELECT /*+ RESULT_CACHE */ * FROM dual"
+        );
+    }
+
+    #[test]
+    fn hints_skip_leading_comments() {
+        assert_eq!(
+            inject_hint("-- totals\n/* report */ SELECT 1 FROM dual", "RESULT_CACHE"),
+            "-- totals\n/* report */ SELECT /*+ RESULT_CACHE */ 1 FROM dual"
+        );
+    }
+
+    #[test]
+    fn hints_go_in_the_main_query_after_with() {
+        assert_eq!(
+            inject_hint(
+                "WITH t AS (SELECT 'select' x FROM dual) SELECT x FROM t",
+                "RESULT_CACHE"
+            ),
+            "WITH t AS (SELECT 'select' x FROM dual) SELECT /*+ RESULT_CACHE */ x FROM t"
+        );
+    }
+
+    #[test]
+    fn env_refs_are_resolved() {
+        std::env::set_var("TESTANGEL_ORACLE_TEST_USER", "scott");
+        std::env::set_var("TESTANGEL_ORACLE_TEST_HOST", "db");
+        assert_eq!(
+            resolve_env_refs(
+                "${env:TESTANGEL_ORACLE_TEST_USER}@${env:TESTANGEL_ORACLE_TEST_HOST}/xe"
+            )
+            .unwrap(),
+            "scott@db/xe"
+        );
+        assert_eq!(resolve_env_refs("no references").unwrap(), "no references");
+    }
+
+    #[test]
+    fn unterminated_env_refs_are_left_alone() {
+        assert_eq!(resolve_env_refs("a${env:B").unwrap(), "a${env:B");
+    }
+
+    #[test]
+    fn missing_env_vars_are_reported() {
+        std::env::remove_var("TESTANGEL_ORACLE_TEST_MISSING");
+        assert!(matches!(
+            resolve_env_refs("${env:TESTANGEL_ORACLE_TEST_MISSING}"),
+            Err(EngineError::MissingEnvVar(name)) if name == "TESTANGEL_ORACLE_TEST_MISSING"
+        ));
+    }
+
+    #[test]
+    fn unique_constraint_errors_name_the_constraint() {
+        for message in [
+            "ORA-00001: unique constraint (HR.EMP_PK) violated",
+            "ORA-00001: unique constraint (HR.EMP_PK) violated on table HR.EMPLOYEES columns (EMPLOYEE_ID)",
+        ] {
+            assert!(matches!(
+                EngineError::from(db_error(ORA_UNIQUE_CONSTRAINT, message)),
+                EngineError::UniqueConstraintViolated(name) if name == "HR.EMP_PK"
+            ));
+        }
+    }
+
+    #[test]
+    fn invalid_identifier_errors_name_the_identifier() {
+        assert!(matches!(
+            EngineError::from(db_error(ORA_INVALID_IDENTIFIER, "ORA-00904: \"SALARYX\": invalid identifier")),
+            EngineError::UnknownIdentifier(name) if name == "SALARYX"
+        ));
+        assert!(matches!(
+            EngineError::from(db_error(ORA_INVALID_IDENTIFIER, "ORA-00904: \"E\".\"SALARYX\": invalid identifier")),
+            EngineError::UnknownIdentifier(name) if name == "E.SALARYX"
+        ));
+    }
+
+    #[test]
+    fn table_not_found_errors_name_the_table_when_given() {
+        assert!(matches!(
+            EngineError::from(db_error(
+                ORA_TABLE_NOT_FOUND,
+                "ORA-00942: table or view does not exist"
+            )),
+            EngineError::TableOrViewNotFound
+        ));
+        assert!(matches!(
+            EngineError::from(db_error(
+                ORA_TABLE_NOT_FOUND,
+                "ORA-00942: table or view \"HR\".\"EMPLOYEEZ\" does not exist"
+            )),
+            EngineError::TableNotFound(name) if name == "HR.EMPLOYEEZ"
+        ));
+    }
+
+    #[test]
+    fn other_database_errors_are_kept() {
+        assert!(matches!(
+            EngineError::from(db_error(1722, "ORA-01722: invalid number")),
+            EngineError::Oracle(_)
+        ));
+    }
+
+    #[test]
+    fn object_names_are_quoted() {
+        assert_eq!(quote_object_name("audit_trg").unwrap(), "\"AUDIT_TRG\"");
+        assert_eq!(
+            quote_object_name(" hr.emp$audit# ").unwrap(),
+            "\"HR\".\"EMP$AUDIT#\""
+        );
+    }
+
+    #[test]
+    fn object_names_that_arent_identifiers_are_rejected() {
+        for name in [
+            "",
+            "  ",
+            "a\"; DROP TABLE x",
+            "HR.",
+            ".EMP",
+            "x.y.z",
+            "emp trg",
+            "emp;",
+        ] {
+            assert!(
+                matches!(
+                    quote_object_name(name),
+                    Err(EngineError::InvalidIdentifier(_))
+                ),
+                "{name:?} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn crypto_algorithms_are_converted() {
+        assert_eq!(
+            crypto_type(" aes256/cbc/pkcs5 ").unwrap(),
+            "DBMS_CRYPTO.ENCRYPT_AES256 + DBMS_CRYPTO.CHAIN_CBC + DBMS_CRYPTO.PAD_PKCS5"
+        );
+        assert_eq!(
+            crypto_type("3DES_2KEY/ECB/NONE").unwrap(),
+            "DBMS_CRYPTO.ENCRYPT_3DES_2KEY + DBMS_CRYPTO.CHAIN_ECB + DBMS_CRYPTO.PAD_NONE"
+        );
+    }
+
+    #[test]
+    fn unknown_crypto_algorithms_are_rejected() {
+        for algorithm in [
+            "",
+            "AES256",
+            "AES256/CBC",
+            "AES256/CBC/PKCS5/NONE",
+            "RC4/CBC/PKCS5",
+            "AES256/CBC/PKCS5 + 1",
+        ] {
+            assert!(
+                matches!(
+                    crypto_type(algorithm),
+                    Err(EngineError::InvalidCryptoAlgorithm(_))
+                ),
+                "{algorithm:?} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+    }
+
+    #[test]
+    fn number_text_keeps_every_digit() {
+        assert_eq!(number_text_to_json("0.5".to_string()), json!(0.5));
+        assert_eq!(number_text_to_json("-12.25".to_string()), json!(-12.25));
+        assert_eq!(
+            number_text_to_json("123456789012345678901234567890".to_string()),
+            json!("123456789012345678901234567890")
+        );
+        assert_eq!(
+            number_text_to_json("0.12345678901234567890123".to_string()),
+            json!("0.12345678901234567890123")
+        );
+    }
+
+    fn schema(objects: &[(&'static str, &str, &str)]) -> SchemaMetadata {
+        let mut metadata = SchemaMetadata::new();
+        for kind in ["tables", "columns", "indexes", "constraints"] {
+            metadata.insert(kind, BTreeMap::new());
+        }
+        for (kind, name, definition) in objects {
+            metadata
+                .get_mut(kind)
+                .unwrap()
+                .insert(name.to_string(), definition.to_string());
+        }
+        metadata
+    }
+
+    #[test]
+    fn identical_schemas_have_no_differences() {
+        let a = schema(&[
+            ("tables", "EMP", ""),
+            ("columns", "EMP.ID", "NUMBER(10,0) NOT NULL"),
+        ]);
+        let (_, differences) = compare_schema_metadata(&a, &a);
+        assert_eq!(differences, 0);
+    }
+
+    #[test]
+    fn schema_differences_are_reported() {
+        let a = schema(&[
+            ("tables", "EMP", ""),
+            ("tables", "OLD_EMP", ""),
+            ("columns", "EMP.NAME", "VARCHAR2(50)"),
+        ]);
+        let b = schema(&[
+            ("tables", "EMP", ""),
+            ("columns", "EMP.NAME", "VARCHAR2(100)"),
+            ("constraints", "EMP C (SALARY) CHECK salary > 0", "ENABLED"),
+        ]);
+        let (report, differences) = compare_schema_metadata(&a, &b);
+        assert_eq!(differences, 3);
+        assert_eq!(report["tables"]["only_in_a"], json!(["OLD_EMP"]));
+        assert_eq!(
+            report["columns"]["different"],
+            json!([{ "name": "EMP.NAME", "a": "VARCHAR2(50)", "b": "VARCHAR2(100)" }])
+        );
+        assert_eq!(
+            report["constraints"]["only_in_b"],
+            json!(["EMP C (SALARY) CHECK salary > 0"])
+        );
+    }
+
+    #[test]
+    fn recorded_values_survive_a_round_trip() {
+        let values = [
+            SqlValue::String("text".to_string()),
+            SqlValue::NChar(NChar("ünïcødé".to_string())),
+            SqlValue::Integer(-42),
+            SqlValue::Decimal(1.5),
+            SqlValue::Boolean(true),
+            SqlValue::Null,
+        ];
+        for value in values {
+            let recorded = value.to_json();
+            let replayed = SqlValue::try_from(&recorded).unwrap();
+            assert_eq!(replayed.to_json(), recorded);
+            assert_eq!(
+                std::mem::discriminant(&replayed),
+                std::mem::discriminant(&value)
+            );
+        }
+    }
+
+    #[test]
+    fn recorded_values_that_cant_be_bound_are_rejected() {
+        for value in [
+            json!([1, 2]),
+            json!({ "nchar": 1 }),
+            json!({ "nchar": "a", "b": "c" }),
+        ] {
+            assert!(matches!(
+                SqlValue::try_from(&value),
+                Err(EngineError::InvalidBatchValue(_))
+            ));
+        }
+    }
+}